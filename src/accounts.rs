@@ -0,0 +1,147 @@
+use crossterm::event::{Event as CrosstermEvent, KeyCode};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{List, ListItem, ListState, StatefulWidget, Widget},
+};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::Config,
+    event::{Event, Outcome},
+    keymap::Action,
+    theme,
+};
+
+/// Lists the accounts in the config file so that the user can switch between them or add another.
+pub struct Accounts {
+    event_sender: Sender<Event>,
+    accounts: Vec<String>,
+    current: Option<String>,
+    selected: usize,
+    status: String,
+    /// The account to log out of once the user confirms
+    confirm_logout: Option<String>,
+}
+
+impl Accounts {
+    pub const fn new(event_sender: Sender<Event>) -> Self {
+        Self {
+            event_sender,
+            accounts: vec![],
+            current: None,
+            selected: 0,
+            status: String::new(),
+            confirm_logout: None,
+        }
+    }
+
+    /// Reloads the accounts from the config file and selects `current`, the logged in account.
+    pub fn start(&mut self, current: Option<String>) {
+        info!("Starting accounts component");
+        self.status.clear();
+        self.accounts = match Config::load() {
+            Ok(config) => config.accounts.into_keys().collect(),
+            Err(err) => {
+                error!("Unable to load config file: {err:#}");
+                self.status = format!("Unable to load config file: {err}");
+                vec![]
+            }
+        };
+        self.selected = current
+            .as_ref()
+            .and_then(|current| self.accounts.iter().position(|name| name == current))
+            .unwrap_or_default();
+        self.current = current;
+        self.confirm_logout = None;
+    }
+
+    /// Shows the result of a background action such as logging out.
+    pub fn show_message(&mut self, message: String) {
+        self.status = message;
+    }
+
+    /// Whether a logout is waiting for the user to press `y`, which takes every key.
+    pub const fn is_confirming(&self) -> bool {
+        self.confirm_logout.is_some()
+    }
+
+    /// Runs an action from the keymap, moving `count` accounts where that makes sense.
+    pub fn handle_action(&mut self, action: Action, count: usize) -> Outcome {
+        match action {
+            Action::Down => {
+                self.selected = self
+                    .selected
+                    .saturating_add(count)
+                    .min(self.accounts.len().saturating_sub(1));
+            }
+            Action::Up => {
+                self.selected = self.selected.saturating_sub(count);
+            }
+            Action::Select => {
+                if let Some(name) = self.accounts.get(self.selected) {
+                    self.send_event(Event::SwitchAccount(name.clone()));
+                }
+            }
+            Action::AddAccount => {
+                self.send_event(Event::AddAccount);
+            }
+            Action::Logout => {
+                if let Some(name) = self.accounts.get(self.selected) {
+                    self.status = format!("Log out of {name} and revoke its access? (y/n)");
+                    self.confirm_logout = Some(name.clone());
+                }
+            }
+            _ => return Outcome::Ignored,
+        }
+        Outcome::Handled
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Outcome {
+        let Event::Crossterm(CrosstermEvent::Key(key)) = event else {
+            return Outcome::Ignored;
+        };
+        let Some(name) = self.confirm_logout.take() else {
+            return Outcome::Ignored;
+        };
+        if key.code == KeyCode::Char('y') {
+            self.status = format!("Logging out of {name}...");
+            self.send_event(Event::Logout(name));
+        } else {
+            self.status.clear();
+        }
+        Outcome::Handled
+    }
+
+    fn send_event(&self, event: Event) {
+        if let Err(err) = self.event_sender.try_send(event) {
+            warn!("unable to send event: {err}");
+        }
+    }
+
+    pub const fn title() -> &'static str {
+        "Accounts"
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+impl Widget for &Accounts {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let theme = theme::current();
+        let items = self.accounts.iter().map(|name| {
+            if self.current.as_ref() == Some(name) {
+                ListItem::new(format!("● {name}")).style(Style::default().fg(theme.accent))
+            } else {
+                ListItem::new(format!("  {name}"))
+            }
+        });
+        let list = List::new(items).highlight_style(theme.highlight());
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(list, area, buf, &mut state);
+    }
+}
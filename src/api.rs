@@ -0,0 +1,39 @@
+//! Requests for parts of the Mastodon API that neither `mastodon_async` nor megalodon expose.
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::WrapErr, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::authentication;
+
+/// A single revision of a status, as returned by `GET /api/v1/statuses/:id/history`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StatusEdit {
+    pub content: String,
+    pub spoiler_text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Fetches every revision of a status, oldest first.
+pub async fn status_history(auth: &authentication::State, id: &str) -> Result<Vec<StatusEdit>> {
+    get(auth, &format!("/api/v1/statuses/{id}/history"))
+        .await
+        .wrap_err("failed to load status history")
+}
+
+/// Makes an authenticated GET request and deserializes the JSON response.
+async fn get<T: DeserializeOwned>(auth: &authentication::State, path: &str) -> Result<T> {
+    let data = &auth.mastodon.data;
+    let url = format!("{}{path}", data.base);
+    let response = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(&data.token)
+        .send()
+        .await
+        .wrap_err_with(|| format!("request to {url} failed"))?
+        .error_for_status()?;
+    response
+        .json()
+        .await
+        .wrap_err_with(|| format!("invalid response from {url}"))
+}
@@ -1,37 +1,58 @@
 use color_eyre::{eyre::WrapErr, Result};
-use crossterm::event::{Event::Key, KeyCode::Char};
 use ratatui::DefaultTerminal;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace};
 
 use crate::{
+    cli::Overrides,
     event::{Event, Events, Outcome},
     logging::LogCollector,
     root::Root,
+    settings::{self, Settings},
 };
 
 pub struct App {
     shutdown: CancellationToken,
     events: Events,
     root: Root,
+    /// The account to log in to at startup instead of the current account
+    account: Option<String>,
+    /// The settings that the app started with, which the settings watcher compares changes to
+    settings: Settings,
+    /// The command line flags, applied again whenever the settings file is reloaded
+    overrides: Overrides,
 }
 
 impl App {
-    pub fn new(logs: LogCollector) -> Self {
+    pub fn new(
+        logs: LogCollector,
+        settings: Settings,
+        account: Option<String>,
+        overrides: Overrides,
+    ) -> Self {
         let events = Events::new();
-        let root = Root::new(events.tx.clone(), logs);
         let shutdown = CancellationToken::new();
+        let root = Root::new(events.tx.clone(), logs, shutdown.clone(), settings.clone());
         Self {
             events,
             root,
             shutdown,
+            account,
+            settings,
+            overrides,
         }
     }
 
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
         info!("Starting application");
         self.events.start();
-        self.root.start();
+        let overrides = self.overrides.clone();
+        tokio::spawn(settings::watch(
+            self.events.tx.clone(),
+            self.settings.clone(),
+            move |settings| overrides.apply(settings),
+        ));
+        self.root.start(self.account.take());
         self.main_loop(&mut terminal)
             .await
             .wrap_err("Running main loop failed")?;
@@ -72,11 +93,6 @@ impl App {
             _ => {
                 if self.root.handle_event(&event).await == Outcome::Handled {
                     debug!(?event, "event handled by root component");
-                } else if let Event::Crossterm(Key(key)) = event {
-                    if key.code == Char('q') {
-                        debug!("received quit key");
-                        self.shutdown.cancel();
-                    }
                 }
             }
         }
@@ -1,76 +1,286 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    future::Future,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 
-use color_eyre::{eyre::WrapErr, Result};
-use crossterm::event::{Event as CrosstermEvent, KeyCode};
-use mastodon_async::{
-    prelude::Account, registration::Registered, scopes::Scopes, Mastodon, Registration,
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
 };
+use crossterm::event::{Event as CrosstermEvent, KeyCode};
+use mastodon_async::{data::Data, prelude::Account, scopes::Scopes, Mastodon, Registration};
+use megalodon::{Megalodon, SNS};
+use qrcode::{render::unicode::Dense1x2, QrCode};
 use ratatui::{
     prelude::*,
-    widgets::{Paragraph, Widget},
+    widgets::{Paragraph, Widget, Wrap},
 };
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
     Mutex,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn};
 use tui_input::{backend::crossterm::EventHandler, Input};
 
 use crate::{
-    config::Config,
+    config::{account_name, AccountConfig, Clients, Config},
+    crypto,
     event::{Event, Outcome},
+    instance::{self, Instance},
+    oauth::{self, AuthorizationRequest, ClientApp},
+    settings, theme,
 };
 
+/// The redirect uri that makes the server display the authorization code instead of redirecting
+const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
 #[derive(Debug)]
 pub struct Authentication {
     event_sender: Sender<Event>,
     server_url_input: Input,
-    server_url_sender: Sender<String>,
-    server_url_receiver: Arc<Mutex<Receiver<String>>>,
-    error: Arc<RwLock<Option<String>>>,
+    server_url_sender: Sender<(String, Flow)>,
+    code_input: Input,
+    code_sender: Sender<String>,
+    passphrase_input: Input,
+    passphrase_sender: Sender<String>,
+    prompts: Prompts,
+    flow: Flow,
+    /// Explains why the user has to sign in again, e.g. a missing scope
+    notice: Option<String>,
     state: Arc<RwLock<Option<State>>>,
+    /// Cancels the running login task, e.g. when the user backs out of adding an account
+    cancel: CancellationToken,
+    /// Cancelled when the app shuts down, which also cancels the login task
+    shutdown: CancellationToken,
+}
+
+/// How the user authorizes tooters and hands the authorization code back to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Open a browser and receive the code on a local callback server
+    Browser,
+    /// Show the authorize url (and a QR code) and have the user paste the code, for SSH and
+    /// headless sessions where a local browser can't reach the callback server
+    Manual,
+}
+
+/// State shared by the login task and the authentication screen: the channels that the task waits
+/// on for input, and what it shows to the user while waiting.
+#[derive(Debug, Clone)]
+struct Prompts {
+    server_url_receiver: Arc<Mutex<Receiver<(String, Flow)>>>,
+    code_receiver: Arc<Mutex<Receiver<String>>>,
+    passphrase_receiver: Arc<Mutex<Receiver<String>>>,
+    /// Why the login task is waiting for the passphrase, if it is
+    passphrase_prompt: Arc<RwLock<Option<PassphrasePrompt>>>,
+    /// The passphrase that encrypts the access tokens, once the user has entered it
+    passphrase: Arc<RwLock<Option<String>>>,
+    /// The server that the user entered, which they confirm by pressing Enter again
+    instance: Arc<RwLock<Option<Instance>>>,
+    /// The url that the user must visit when using the manual flow, while waiting for the code
+    authorize_url: Arc<RwLock<Option<String>>>,
+    error: Arc<RwLock<Option<String>>>,
+    settings: settings::Auth,
+}
+
+/// Why the login task needs the passphrase that encrypts access tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PassphrasePrompt {
+    /// Decrypt the saved access token of the named account
+    Unlock(String),
+    /// Choose a passphrase to encrypt a new access token with
+    Choose,
+}
+
+/// Which account to log in to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Login {
+    /// The current account from the config file, or a new account if there are none
+    Current,
+    /// A named account from the config file
+    Account(String),
+    /// A new account, which is added to the config file
+    New,
+    /// Sign in again to the logged in account's server, asking for the given scope in addition to
+    /// the configured scopes
+    Reauthorize(String),
+    /// Sign in again to the named account, whose access token has expired or was revoked
+    Renew(String),
+}
+
+/// The server rejected the saved access token of the named account.
+#[derive(Debug)]
+struct SessionExpired(String);
+
+impl std::fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the session for {} has expired or was revoked", self.0)
+    }
+}
+
+/// Whether `err` was caused by the server rejecting the access token (HTTP 401 Unauthorized), e.g.
+/// because it expired or was revoked. Understands errors from reqwest, megalodon and
+/// `mastodon_async`.
+pub fn is_unauthorized(err: &color_eyre::Report) -> bool {
+    const UNAUTHORIZED: u16 = 401;
+    err.chain().any(|err| {
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            return err
+                .status()
+                .is_some_and(|status| status.as_u16() == UNAUTHORIZED);
+        }
+        match err.downcast_ref::<megalodon::error::Error>() {
+            Some(megalodon::error::Error::OwnError(err)) => {
+                return err.status == Some(UNAUTHORIZED)
+            }
+            Some(megalodon::error::Error::RequestError(err)) => {
+                return err
+                    .status()
+                    .is_some_and(|status| status.as_u16() == UNAUTHORIZED);
+            }
+            _ => {}
+        }
+        matches!(
+            err.downcast_ref::<mastodon_async::Error>(),
+            Some(mastodon_async::Error::Api { status, .. }) if status.as_u16() == UNAUTHORIZED
+        )
+    })
 }
 
 #[derive(Debug, Clone)]
 pub struct State {
     pub mastodon: Mastodon,
-    pub config: Config,
+    /// The name of the account in the config file, e.g. `user@mastodon.social`
+    pub name: String,
     pub account: Account,
+    /// The space separated scopes granted to the access token, or `None` if unknown
+    pub scopes: Option<String>,
+}
+
+impl State {
+    /// Whether the access token grants `scope`. Tokens with unknown scopes are assumed to grant it.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|granted| oauth::grants(granted, scope))
+    }
+
+    /// Returns a megalodon client for the authenticated account.
+    ///
+    /// megalodon exposes parts of the API that `mastodon_async` does not (e.g. `edited_at` on
+    /// statuses), so the two share the same credentials.
+    pub fn client(&self) -> Result<Box<dyn Megalodon + Send + Sync>> {
+        client(&self.mastodon.data)
+    }
+}
+
+/// Returns a megalodon client that uses the credentials in `data`.
+pub fn client(data: &Data) -> Result<Box<dyn Megalodon + Send + Sync>> {
+    megalodon::generator(
+        SNS::Mastodon,
+        data.base.to_string(),
+        Some(data.token.to_string()),
+        None,
+    )
+    .wrap_err("unable to create megalodon client")
 }
 
 impl Authentication {
     pub fn new(
         event_sender: Sender<Event>,
         authentication_data: Arc<RwLock<Option<State>>>,
+        shutdown: CancellationToken,
+        settings: settings::Auth,
     ) -> Self {
         let (server_url_sender, server_url_receiver) = mpsc::channel(1);
+        let (code_sender, code_receiver) = mpsc::channel(1);
+        let (passphrase_sender, passphrase_receiver) = mpsc::channel(1);
         Self {
             event_sender,
             server_url_input: Input::new("https://mastodon.social".to_string()),
             server_url_sender,
-            server_url_receiver: Arc::new(Mutex::new(server_url_receiver)),
-            error: Arc::new(RwLock::new(None)),
+            code_input: Input::default(),
+            code_sender,
+            passphrase_input: Input::default(),
+            passphrase_sender,
+            prompts: Prompts {
+                server_url_receiver: Arc::new(Mutex::new(server_url_receiver)),
+                code_receiver: Arc::new(Mutex::new(code_receiver)),
+                passphrase_receiver: Arc::new(Mutex::new(passphrase_receiver)),
+                passphrase_prompt: Arc::new(RwLock::new(None)),
+                passphrase: Arc::new(RwLock::new(None)),
+                instance: Arc::new(RwLock::new(None)),
+                authorize_url: Arc::new(RwLock::new(None)),
+                error: Arc::new(RwLock::new(None)),
+                settings,
+            },
+            flow: Flow::Browser,
+            notice: None,
             state: authentication_data,
+            cancel: shutdown.child_token(),
+            shutdown,
         }
     }
 
+    /// Applies settings that changed while the app is running, from the next login on.
+    pub fn apply_settings(&mut self, settings: settings::Auth) {
+        self.prompts.settings = settings;
+    }
+
     pub fn title(&self) -> String {
         String::from("Authenticating at ") + self.server_url_input.value()
     }
 
     pub async fn handle_event(&mut self, event: &Event) -> Outcome {
         trace!(?event, "AuthenticationComponent::handle_event");
+        let awaiting_code = self.awaiting_code();
+        let awaiting_passphrase = self.awaiting_passphrase();
         match event {
+            Event::Crossterm(CrosstermEvent::Key(key_event))
+                if key_event.code == KeyCode::Enter && awaiting_passphrase =>
+            {
+                let passphrase = self.passphrase_input.value().to_string();
+                self.passphrase_sender.send(passphrase).await.ok();
+                self.passphrase_input.reset();
+                Outcome::Handled
+            }
+            Event::Crossterm(e) if awaiting_passphrase => {
+                self.passphrase_input.handle_event(e);
+                Outcome::Handled
+            }
+            Event::Crossterm(CrosstermEvent::Key(key_event))
+                if key_event.code == KeyCode::Enter && awaiting_code =>
+            {
+                let code = self.code_input.value().trim().to_string();
+                self.code_sender.send(code).await.ok();
+                self.code_input.reset();
+                Outcome::Handled
+            }
             Event::Crossterm(CrosstermEvent::Key(key_event))
                 if key_event.code == KeyCode::Enter =>
             {
                 self.server_url_sender
                     .clone()
-                    .send(self.server_url_input.value().to_string())
+                    .send((self.server_url_input.value().to_string(), self.flow))
                     .await
                     .ok();
                 Outcome::Handled
             }
+            Event::Crossterm(CrosstermEvent::Key(key_event))
+                if key_event.code == KeyCode::Tab && !awaiting_code =>
+            {
+                self.flow = match self.flow {
+                    Flow::Browser => Flow::Manual,
+                    Flow::Manual => Flow::Browser,
+                };
+                Outcome::Handled
+            }
+            Event::Crossterm(e) if awaiting_code => {
+                self.code_input.handle_event(e);
+                Outcome::Handled
+            }
             Event::Crossterm(e) => {
                 self.server_url_input.handle_event(e);
                 Outcome::Handled
@@ -79,137 +289,493 @@ impl Authentication {
         }
     }
 
-    pub fn start(&mut self) {
-        info!("Starting authentication component");
-        let error = Arc::clone(&self.error);
+    /// Whether the manual flow is waiting for the user to paste the authorization code.
+    fn awaiting_code(&self) -> bool {
+        self.prompts
+            .authorize_url
+            .read()
+            .expect("lock poisoned")
+            .is_some()
+    }
+
+    /// Whether the login task is waiting for the user to enter the passphrase.
+    fn awaiting_passphrase(&self) -> bool {
+        self.prompts
+            .passphrase_prompt
+            .read()
+            .expect("lock poisoned")
+            .is_some()
+    }
+
+    /// The passphrase that encrypts the access tokens, if the user has entered it.
+    pub fn passphrase(&self) -> Option<String> {
+        self.prompts
+            .passphrase
+            .read()
+            .expect("lock poisoned")
+            .clone()
+    }
+
+    /// Starts logging in, replacing any login that is already in progress.
+    pub fn start(&mut self, login: Login) {
+        info!(?login, "Starting authentication component");
+        self.cancel();
+        self.cancel = self.shutdown.child_token();
+        *self.prompts.error.write().expect("lock poisoned") = None;
+        *self.prompts.instance.write().expect("lock poisoned") = None;
+        *self.prompts.authorize_url.write().expect("lock poisoned") = None;
+        *self
+            .prompts
+            .passphrase_prompt
+            .write()
+            .expect("lock poisoned") = None;
+        self.code_input.reset();
+        self.passphrase_input.reset();
+        self.notice = None;
+        match &login {
+            Login::Reauthorize(scope) => {
+                self.notice = Some(format!(
+                    "Tooters needs the {scope} permission, which this account has not granted. \
+                     Sign in again to grant it."
+                ));
+                if let Some(auth) = self.state.read().expect("lock poisoned").as_ref() {
+                    self.server_url_input = Input::new(auth.mastodon.data.base.to_string());
+                }
+            }
+            Login::Renew(name) => {
+                self.notice = Some(format!(
+                    "Your session for {name} has expired or was revoked. Sign in again to \
+                     continue where you left off."
+                ));
+                let config = Config::load().unwrap_or_default();
+                if let Some(account) = config.accounts.get(name) {
+                    self.server_url_input = Input::new(account.data.base.to_string());
+                }
+            }
+            Login::Current | Login::Account(_) | Login::New => {}
+        }
+        let cancel = self.cancel.clone();
         let authentication_data = Arc::clone(&self.state);
-        let server_url_receiver = self.server_url_receiver.clone();
+        let prompts = self.prompts.clone();
         let event_sender = self.event_sender.clone();
         tokio::spawn(async move {
-            loop {
-                let server_url_receiver = server_url_receiver.clone();
-                let authentication_data = authentication_data.clone();
-                match load_config_or_authorize(server_url_receiver, authentication_data).await {
-                    Ok(()) => break,
-                    Err(e) => {
-                        warn!("Authentication attempt failed: {:#}", e);
-                        display_error(&e, &error);
+            let mut login = login;
+            let login_loop = async {
+                loop {
+                    let authentication_data = authentication_data.clone();
+                    let result = log_in(&login, &prompts, authentication_data).await;
+                    *prompts.authorize_url.write().expect("lock poisoned") = None;
+                    match result {
+                        Ok(()) => break Event::AuthenticationSuccess,
+                        Err(e) => {
+                            warn!("Authentication attempt failed: {:#}", e);
+                            if let Some(SessionExpired(name)) = e.downcast_ref() {
+                                break Event::SessionExpired(name.clone());
+                            }
+                            display_error(&e, &prompts.error);
+                            // retrying a saved account would fail the same way, so ask for a server
+                            if !matches!(login, Login::Reauthorize(_) | Login::Renew(_)) {
+                                login = Login::New;
+                            }
+                        }
                     }
                 }
-            }
-            if let Err(err) = event_sender.send(Event::AuthenticationSuccess).await {
-                error!("Error sending authentication success message: {:?}", err);
+            };
+            let event = tokio::select! {
+                () = cancel.cancelled() => {
+                    info!("Authentication cancelled");
+                    return;
+                }
+                event = login_loop => event,
+            };
+            if let Err(err) = event_sender.send(event).await {
+                error!("Error sending authentication result: {:?}", err);
             }
         });
     }
+
+    /// Stops the login in progress, if any.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Logs out of the named account: revokes its access token and removes it from the config file.
+///
+/// The account is removed even if the token can't be revoked (e.g. because it was already revoked
+/// on the server), as the aim is to leave no usable credentials behind. Revoking an encrypted
+/// token needs the `passphrase`. Returns why the token was not revoked, if it wasn't, as it stays
+/// valid on the server.
+pub async fn log_out(name: &str, passphrase: Option<&str>) -> Result<Option<color_eyre::Report>> {
+    let mut config = Config::load()?;
+    let account = config
+        .accounts
+        .remove(name)
+        .ok_or_else(|| eyre!("no account named {name}"))?;
+    let revoked = match account.decrypted_data(passphrase) {
+        Ok(data) => oauth::revoke_token(&data).await,
+        Err(err) => Err(err),
+    };
+    let revoke_error = match revoked {
+        Ok(()) => {
+            info!(name, "Revoked access token");
+            None
+        }
+        Err(err) => {
+            warn!(name, "Unable to revoke access token: {err:#}");
+            Some(err)
+        }
+    };
+    if config.current.as_deref() == Some(name) {
+        config.current = None;
+    }
+    config.save()?;
+    info!(name, "Logged out");
+    Ok(revoke_error)
 }
 
 fn display_error(e: &color_eyre::eyre::Error, error: &Arc<RwLock<Option<String>>>) {
     *error.write().expect("lock poisoned") = Some(e.to_string());
 }
 
-async fn load_config_or_authorize(
-    server_url_receiver: Arc<Mutex<Receiver<String>>>,
+async fn log_in(
+    login: &Login,
+    prompts: &Prompts,
     authentication_data: Arc<RwLock<Option<State>>>,
 ) -> Result<()> {
-    let (mastodon, config) = match Config::load() {
-        Ok(config) => (Mastodon::from(config.data.clone()), config),
-        Err(err) => {
-            info!("Attempting authorization flow. {}", err);
-            let mastodon = authorize(server_url_receiver)
-                .await
-                .wrap_err("unable to authorize")?;
-            info!("Authorization successful");
-            let config = Config::from(mastodon.data.clone());
-            if let Err(err) = config.save() {
-                // this is not fatal, but it means that we need to re-authenticate next time
-                error!("Unable to save config file: {}", err);
-            }
-            (mastodon, config)
+    // an unreadable config file is an error rather than empty, as logging in saves it
+    let mut config = Config::load()?;
+    let saved = match login {
+        Login::Current => config.current_account(),
+        Login::Account(name) => Some(
+            config
+                .accounts
+                .get_key_value(name)
+                .ok_or_else(|| eyre!("no account named {name}"))?,
+        ),
+        Login::New | Login::Reauthorize(_) | Login::Renew(_) => None,
+    };
+    let (mastodon, scopes, name, encrypted_token) = if let Some((name, saved)) = saved {
+        let mut data = saved.data.clone();
+        if let Some(encrypted) = &saved.encrypted_token {
+            data.token = unlock_token(name, encrypted, prompts).await?.into();
+        }
+        let mastodon = Mastodon::from(data);
+        (
+            mastodon,
+            saved.scopes.clone(),
+            Some(name.clone()),
+            saved.encrypted_token.clone(),
+        )
+    } else {
+        let mut scopes = prompts.settings.scopes()?;
+        if let Login::Reauthorize(scope) = login {
+            scopes = scopes | Scopes::from_str(scope).wrap_err("invalid scope")?;
         }
+        info!(%scopes, "Attempting authorization flow");
+        let (mastodon, granted) = authorize(prompts, scopes)
+            .await
+            .wrap_err("unable to authorize")?;
+        info!(scopes = granted, "Authorization successful");
+        (mastodon, Some(granted), None, None)
     };
 
-    let account = mastodon
-        .verify_credentials()
-        .await
-        .wrap_err("failed to verify credentials")?;
+    let account = match mastodon.verify_credentials().await {
+        Ok(account) => account,
+        Err(err) => {
+            let err = color_eyre::Report::new(err);
+            return Err(match &name {
+                Some(name) if is_unauthorized(&err) => err.wrap_err(SessionExpired(name.clone())),
+                _ => err.wrap_err("failed to verify credentials"),
+            });
+        }
+    };
     info!("Verified credentials. Logged in as {}", account.username);
+
+    // a new token always needs saving, a saved one only when switching accounts
+    let changed = name.is_none();
+    let encrypted_token = match encrypted_token {
+        None if changed && prompts.settings.encrypt_tokens => {
+            encrypt_token(&mastodon.data.token, prompts).await?
+        }
+        encrypted_token => encrypted_token,
+    };
+    let name = name.unwrap_or_else(|| account_name(&account.username, &mastodon.data.base));
+    let changed = changed || config.current.as_ref() != Some(&name);
+    let saved = AccountConfig::new(mastodon.data.clone(), scopes.clone(), encrypted_token);
+    config.add_account(name.clone(), saved);
+    if changed {
+        if let Err(err) = config.save() {
+            // this is not fatal, but it means that we need to re-authenticate next time
+            error!("Unable to save config file: {}", err);
+        }
+    }
+
     *authentication_data.write().expect("lock poisoned") = Some(State {
-        mastodon: mastodon.clone(),
-        config,
+        mastodon,
+        name,
         account,
+        scopes,
     });
     Ok(())
 }
 
-async fn authorize(server_url_receiver: Arc<Mutex<Receiver<String>>>) -> Result<Mastodon> {
-    info!("Waiting for server url...");
-    let server_url = get_server_url(server_url_receiver).await?;
-    info!("Registering Tooters at: {}", server_url);
-    let registered = register_client_app(server_url).await?;
-    info!("Tooters client registered");
-    let auth_code = get_auth_code(&registered).await?;
-    debug!("Auth code: {}", auth_code);
-    let mastodon = complete_registration(&registered, auth_code).await?;
-    debug!("Mastodon: {:?}", mastodon);
-    Ok(mastodon)
+/// Decrypts the saved access token of the named account, asking for the passphrase until the user
+/// enters the right one. Entering no passphrase gives up, so that the user can sign in again.
+async fn unlock_token(name: &str, encrypted: &str, prompts: &Prompts) -> Result<String> {
+    let known = prompts.passphrase.read().expect("lock poisoned").clone();
+    if let Some(token) = known.and_then(|passphrase| crypto::decrypt(&passphrase, encrypted).ok()) {
+        return Ok(token);
+    }
+    loop {
+        let passphrase =
+            get_passphrase(prompts, PassphrasePrompt::Unlock(name.to_string())).await?;
+        if passphrase.is_empty() {
+            bail!("no passphrase entered for {name}");
+        }
+        match crypto::decrypt(&passphrase, encrypted) {
+            Ok(token) => {
+                *prompts.error.write().expect("lock poisoned") = None;
+                *prompts.passphrase.write().expect("lock poisoned") = Some(passphrase);
+                return Ok(token);
+            }
+            Err(err) => display_error(&err, &prompts.error),
+        }
+    }
+}
+
+/// Encrypts a new access token with the passphrase, asking the user to choose one if they have not
+/// entered it yet. Returns `None` if the user chooses to store the token unencrypted.
+async fn encrypt_token(token: &str, prompts: &Prompts) -> Result<Option<String>> {
+    let known = prompts.passphrase.read().expect("lock poisoned").clone();
+    let passphrase = match known {
+        Some(passphrase) => passphrase,
+        None => get_passphrase(prompts, PassphrasePrompt::Choose).await?,
+    };
+    if passphrase.is_empty() {
+        warn!("No passphrase entered, storing the access token unencrypted");
+        return Ok(None);
+    }
+    let encrypted = crypto::encrypt(&passphrase, token)?;
+    *prompts.passphrase.write().expect("lock poisoned") = Some(passphrase);
+    Ok(Some(encrypted))
+}
+
+/// Get the passphrase from the user by asking them to enter it in the terminal
+async fn get_passphrase(prompts: &Prompts, prompt: PassphrasePrompt) -> Result<String> {
+    info!(?prompt, "Waiting for passphrase...");
+    *prompts.passphrase_prompt.write().expect("lock poisoned") = Some(prompt);
+    let mut passphrase_receiver = prompts.passphrase_receiver.lock().await;
+    // discard passphrases entered for an earlier attempt
+    while passphrase_receiver.try_recv().is_ok() {}
+    let passphrase = passphrase_receiver
+        .recv()
+        .await
+        .ok_or_else(|| eyre!("Error getting passphrase"));
+    *prompts.passphrase_prompt.write().expect("lock poisoned") = None;
+    passphrase
+}
+
+/// Authorizes tooters with the server that the user enters, returning the client and the scopes
+/// that were granted.
+async fn authorize(prompts: &Prompts, scopes: Scopes) -> Result<(Mastodon, String)> {
+    let (server_url, flow) = get_instance(prompts).await?;
+    authorize_at(&server_url, flow, &prompts.settings, scopes, |auth_url| {
+        get_pasted_code(auth_url, prompts)
+    })
+    .await
+}
+
+/// Authorizes tooters with the server at `server_url`, returning the client and the scopes that
+/// were granted. The manual flow calls `paste_code` with the authorize url to get the code that the
+/// server shows after the user authorizes tooters.
+pub async fn authorize_at<F, Fut>(
+    server_url: &str,
+    flow: Flow,
+    settings: &settings::Auth,
+    scopes: Scopes,
+    paste_code: F,
+) -> Result<(Mastodon, String)>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let server_url = server_url.to_string();
+    let scope_names = scopes.to_string();
+    // an unreadable file is an error rather than empty, as registering saves it
+    let mut clients = Clients::load()?;
+    let saved = clients
+        .servers
+        .get(&server_url)
+        .filter(|app| app.allows(&scope_names))
+        .cloned();
+    // the callback server is started first so that the redirect uri contains its port
+    let callback_server = match flow {
+        Flow::Browser => Some(bind_callback_server(settings, saved.as_ref()).await?),
+        Flow::Manual => None,
+    };
+    let redirect_uri = callback_server.as_ref().map_or_else(
+        || OOB_REDIRECT_URI.to_string(),
+        server::CallbackServer::redirect_uri,
+    );
+    let saved = saved.filter(|app| app.redirect_uris.contains(&redirect_uri));
+    let reused = saved.is_some();
+    let app = if let Some(app) = saved {
+        info!("Reusing the Tooters client registered at: {}", server_url);
+        app
+    } else {
+        info!("Registering Tooters at: {}", server_url);
+        let app = register_client_app(&server_url, &redirect_uri, scopes).await?;
+        info!("Tooters client registered");
+        clients.servers.insert(server_url.clone(), app.clone());
+        if let Err(err) = clients.save() {
+            // this is not fatal, but it means that we register again next time
+            error!("Unable to save registered clients: {err:#}");
+        }
+        app
+    };
+    let request = AuthorizationRequest::new(redirect_uri, scope_names);
+    let auth_url = app.authorize_url(&request)?;
+    let auth_code = match callback_server {
+        Some(server) => get_auth_code(&auth_url, server, &request).await?,
+        None => paste_code(auth_url).await?,
+    };
+    debug!("Received auth code");
+    let (mastodon, granted) = match app.exchange_code(&request, &auth_code).await {
+        Ok(token) => token,
+        Err(err) => {
+            if reused && oauth::is_rejected_client(&err) {
+                // the app was removed from the server, so register it again next time
+                clients.servers.remove(&server_url);
+                clients.save().ok();
+            }
+            return Err(err).wrap_err("Unable to complete registration with the auth code");
+        }
+    };
+    Ok((mastodon, granted))
+}
+
+/// Binds the callback server to the port from the settings, or else to the port of the saved
+/// registration so that its redirect uri can be reused, falling back to a random free port.
+async fn bind_callback_server(
+    settings: &settings::Auth,
+    saved: Option<&ClientApp>,
+) -> Result<server::CallbackServer> {
+    if let Some(port) = settings.callback_port {
+        return server::CallbackServer::bind(port).await;
+    }
+    if let Some(port) = saved.and_then(ClientApp::callback_port) {
+        match server::CallbackServer::bind(port).await {
+            Ok(server) => return Ok(server),
+            Err(err) => warn!("Unable to reuse callback port {port}: {err:#}"),
+        }
+    }
+    server::CallbackServer::bind(0).await
+}
+
+/// Get the server from the user, showing what the server says about itself before continuing
+/// so that the user can check that it is the right one.
+async fn get_instance(prompts: &Prompts) -> Result<(String, Flow)> {
+    loop {
+        info!("Waiting for server url...");
+        let (input, flow) = get_server_url(&prompts.server_url_receiver).await?;
+        let server_url = instance::normalize_server_url(&input)?;
+        let confirmed = prompts
+            .instance
+            .read()
+            .expect("lock poisoned")
+            .as_ref()
+            .is_some_and(|instance| instance.base == server_url);
+        if confirmed {
+            return Ok((server_url, flow));
+        }
+        *prompts.instance.write().expect("lock poisoned") = None;
+        let instance = instance::probe(&server_url).await?;
+        info!(
+            title = instance.title,
+            version = instance.version,
+            "Found instance"
+        );
+        *prompts.error.write().expect("lock poisoned") = None;
+        *prompts.instance.write().expect("lock poisoned") = Some(instance);
+    }
 }
 
 /// Get the server url from the user by asking them to enter it in the terminal
-async fn get_server_url(server_url_receiver: Arc<Mutex<Receiver<String>>>) -> Result<String> {
-    let mutex = server_url_receiver.clone();
-    let mut server_url_receiver = mutex.lock().await;
+async fn get_server_url(
+    server_url_receiver: &Mutex<Receiver<(String, Flow)>>,
+) -> Result<(String, Flow)> {
+    let mut server_url_receiver = server_url_receiver.lock().await;
     server_url_receiver
         .recv()
         .await
         .ok_or_else(|| color_eyre::eyre::Error::msg("Error getting server url"))
 }
 
-/// Register the client with the server
-async fn register_client_app(server_url: String) -> Result<Registered> {
-    Registration::new(&server_url)
+/// Register the client with the server. The out-of-band redirect uri is always registered so that
+/// the registration can be reused for the manual flow.
+async fn register_client_app(
+    server_url: &str,
+    redirect_uri: &str,
+    scopes: Scopes,
+) -> Result<ClientApp> {
+    let mut redirect_uris = OOB_REDIRECT_URI.to_string();
+    if redirect_uri != OOB_REDIRECT_URI {
+        redirect_uris = format!("{redirect_uris}\n{redirect_uri}");
+    }
+    let registered = Registration::new(server_url)
         .client_name("Tooters")
         .website("https://github.com/joshka/tooters")
-        .redirect_uris("http://localhost:7007/callback")
-        .scopes(Scopes::all())
+        .redirect_uris(redirect_uris)
+        .scopes(scopes)
         .build()
         .await
-        .wrap_err(format!("unable to register tooters with {server_url}"))
+        .wrap_err(format!("unable to register tooters with {server_url}"))?;
+    Ok(ClientApp::from(registered))
 }
 
-/// Launch a browser to the authorization url and get the auth code from the user
-/// Launch a server for the url redirect
-async fn get_auth_code(registered: &Registered) -> Result<String> {
-    let auth_url = registered
-        .authorize_url()
-        .wrap_err("Registered.authorize_url() is a result but it can't fail ¯\\_(ツ)_/¯")?;
-    if webbrowser::open(&auth_url).is_ok() {
+/// Launch a browser to the authorization url and wait for the server to redirect it to the
+/// callback server with the auth code
+async fn get_auth_code(
+    auth_url: &str,
+    server: server::CallbackServer,
+    request: &AuthorizationRequest,
+) -> Result<String> {
+    if webbrowser::open(auth_url).is_ok() {
         info!("Opened browser to {}", auth_url);
     } else {
         warn!("Unable to open browser, please open this url: {}", auth_url);
     };
-    let auth_code = server::get_code()
+    server
+        .get_code(&request.state)
         .await
-        .wrap_err("Error getting auth code from webserver")?;
-    Ok(auth_code)
+        .wrap_err("Error getting auth code from webserver")
 }
 
-async fn complete_registration(registered: &Registered, code: String) -> Result<Mastodon> {
-    registered
-        .complete(code)
+/// Show the authorization url on the authentication screen and wait for the user to paste the code
+/// that the server displays after authorizing tooters
+async fn get_pasted_code(auth_url: String, prompts: &Prompts) -> Result<String> {
+    info!("Waiting for the auth code from {}", auth_url);
+    *prompts.authorize_url.write().expect("lock poisoned") = Some(auth_url);
+    let mut code_receiver = prompts.code_receiver.lock().await;
+    // discard codes entered for an earlier attempt
+    while code_receiver.try_recv().is_ok() {}
+    code_receiver
+        .recv()
         .await
-        .wrap_err("Unable to complete registration with the auth code")
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| eyre!("no auth code entered"))
 }
 
 /// a small webserver to listen for the authentication code callback from the
 /// mastodon server
 mod server {
-    use std::collections::HashMap;
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
 
     use axum::{
         extract::{Query, State},
         http::StatusCode,
-        response::{IntoResponse, Response},
+        response::Html,
         routing::get,
         Router,
     };
@@ -217,107 +783,200 @@ mod server {
         eyre::{eyre, WrapErr},
         Result,
     };
-    use tokio::sync::mpsc::{channel, Sender};
-    use tracing::info;
+    use serde::Deserialize;
+    use tokio::{
+        net::TcpListener,
+        sync::mpsc::{channel, Sender},
+    };
+    use tokio_util::sync::CancellationToken;
+    use tracing::{info, warn};
+
+    /// How long to wait for the user to authorize tooters in the browser
+    const TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
-    /// State for the axum webserver that allows the handler to send a code back
-    /// to the main thread and shutdown the webserver.
+    /// A webserver bound to a local port, which is not yet serving requests.
+    pub struct CallbackServer {
+        listener: TcpListener,
+        address: SocketAddr,
+    }
+
+    /// State for the axum webserver that allows the handler to send the result of the
+    /// authorization back to the login task.
     #[derive(Debug, Clone)]
     struct AppState {
-        code_sender: Sender<String>,
-        shutdown_sender: Sender<()>,
+        result_sender: Sender<Result<String, String>>,
+        expected_state: Arc<str>,
     }
 
-    /// Starts a webserver on port 7007 to listen for an authentication callback.
-    /// Returns the received authentication code when the callback is called.
-    pub async fn get_code() -> Result<String> {
-        let port = 7007;
-        let (code_sender, mut code_receiver) = channel::<String>(1);
-        let (shutdown_sender, mut shutdown_reciever) = channel::<()>(1);
-        let state = AppState {
-            code_sender,
-            shutdown_sender,
-        };
-        info!(
-            "Starting webserver to listen for authentication callback on port {}",
-            port
-        );
-        let router = Router::new()
-            .route("/callback", get(handler))
-            .with_state(state);
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:7007").await?;
-        axum::serve(listener, router)
-            .with_graceful_shutdown(async move {
-                shutdown_reciever.recv().await;
-            })
-            .await
-            .wrap_err("Error running webserver")?;
-        code_receiver
-            .recv()
-            .await
-            .ok_or(eyre!("Error receiving auth code from webserver"))
+    /// The query string of the callback. The server sends either a code or an error.
+    #[derive(Debug, Deserialize)]
+    struct CallbackParams {
+        code: Option<String>,
+        state: Option<String>,
+        error: Option<String>,
+        error_description: Option<String>,
+    }
+
+    impl CallbackServer {
+        /// Binds the server to the given port on the loopback interface, or to a random port if
+        /// the port is 0.
+        pub async fn bind(port: u16) -> Result<Self> {
+            let listener = TcpListener::bind(("127.0.0.1", port))
+                .await
+                .wrap_err_with(|| format!("unable to listen for callbacks on port {port}"))?;
+            let address = listener.local_addr()?;
+            Ok(Self { listener, address })
+        }
+
+        /// The redirect uri to register with the server.
+        pub fn redirect_uri(&self) -> String {
+            format!("http://{}/callback", self.address)
+        }
+
+        /// Serves requests until the callback with the given `state` arrives, and returns its
+        /// auth code. Gives up after [`TIMEOUT`].
+        pub async fn get_code(self, expected_state: &str) -> Result<String> {
+            info!(
+                "Starting webserver to listen for authentication callback on {}",
+                self.address
+            );
+            let (result_sender, mut result_receiver) = channel(1);
+            let state = AppState {
+                result_sender,
+                expected_state: expected_state.into(),
+            };
+            let router = Router::new()
+                .route("/callback", get(handler))
+                .with_state(state);
+            let shutdown = CancellationToken::new();
+            let server = axum::serve(self.listener, router)
+                .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            let result = async {
+                let result = tokio::select! {
+                    result = result_receiver.recv() => result
+                        .ok_or_else(|| eyre!("Error receiving auth code from webserver"))?
+                        .map_err(|err| eyre!("authorization failed: {err}")),
+                    () = tokio::time::sleep(TIMEOUT) => Err(eyre!(
+                        "timed out after {} minutes waiting for authorization",
+                        TIMEOUT.as_secs() / 60
+                    )),
+                };
+                // let the server finish sending the response page before it stops
+                shutdown.cancel();
+                result
+            };
+            let (served, result) = tokio::join!(server, result);
+            served.wrap_err("Error running webserver")?;
+            result
+        }
     }
 
     /// Handles the `/callback` route for the webserver.
-    /// It extracts the authentication code from the query string and sends it to the main thread.
-    /// After that, it sends a shutdown signal to the webserver.
+    /// It checks that the callback belongs to this authorization attempt, then sends the code (or
+    /// the error from the server) back to the login task and shows a page to the user.
     async fn handler(
-        Query(params): Query<HashMap<String, String>>,
+        Query(params): Query<CallbackParams>,
         State(state): State<AppState>,
-    ) -> axum::response::Result<&'static str, AppError> {
-        let code = params.get("code").ok_or(eyre!("No code in query string"))?;
-        state
-            .code_sender
-            .send(code.to_string())
-            .await
-            .wrap_err("Error sending code to main thread")?;
-        state
-            .shutdown_sender
-            .send(())
-            .await
-            .wrap_err("Error sending shutdown signal to webserver")?;
-        Ok("Authentication successful! You can close this window now.")
+    ) -> (StatusCode, Html<String>) {
+        if params.state.as_deref() != Some(&*state.expected_state) {
+            // ignored rather than failing the login, so a forged request can't abort it
+            warn!("Ignoring authentication callback with an unexpected state");
+            return page(
+                StatusCode::BAD_REQUEST,
+                "Authentication failed",
+                "This link does not belong to the current sign in attempt. Please sign in from \
+                 tooters again.",
+            );
+        }
+        let result = match params {
+            CallbackParams {
+                code: Some(code),
+                error: None,
+                ..
+            } => Ok(code),
+            CallbackParams {
+                error,
+                error_description,
+                ..
+            } => Err(error_description
+                .or(error)
+                .unwrap_or_else(|| "no code in callback".to_string())),
+        };
+        let response = match &result {
+            Ok(_) => page(
+                StatusCode::OK,
+                "Authentication successful",
+                "You can close this window and return to tooters.",
+            ),
+            Err(err) => page(StatusCode::BAD_REQUEST, "Authentication failed", err),
+        };
+        if state.result_sender.send(result).await.is_err() {
+            warn!("Authentication callback received after login finished");
+        }
+        response
     }
 
-    /// helper type to convert `eyre::Error`s into responses
-    struct AppError(color_eyre::eyre::Error);
-
-    /// Implements `IntoResponse` for `AppError`, converting it into a response with status code
-    /// 500.
-    impl IntoResponse for AppError {
-        fn into_response(self) -> Response {
-            (StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
-        }
+    fn page(status: StatusCode, title: &str, message: &str) -> (StatusCode, Html<String>) {
+        let message = escape(message);
+        let html = format!(
+            "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>Tooters - \
+             {title}</title></head>\n<body><h1>{title}</h1><p>{message}</p></body>\n</html>\n"
+        );
+        (status, Html(html))
     }
 
-    /// Implements the `From` trait for `AppError`, allowing it to be converted
-    /// from any type implementing `Into<anyhow::Error>`.
-    impl<E> From<E> for AppError
-    where
-        E: Into<color_eyre::eyre::Error>,
-    {
-        fn from(error: E) -> Self {
-            Self(error.into())
-        }
+    /// Escapes text from the callback (e.g. the error description) for use in HTML.
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
     }
 } // mod server
 
 impl Widget for &Authentication {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        use Constraint::Length;
-        let error = &self.error.read().expect("lock poisoned").clone();
+        use Constraint::{Fill, Length};
+        let error = &self.prompts.error.read().expect("lock poisoned").clone();
+        let authorize_url = self
+            .prompts
+            .authorize_url
+            .read()
+            .expect("lock poisoned")
+            .clone();
         let server_url = self.server_url_input.value().to_string();
         let error_height = if error.is_some() { 2 } else { 0 };
-        let [welcome_area, error_area, server_url_area] =
-            Layout::vertical([Length(3), Length(error_height), Length(2)]).areas(area);
-        Paragraph::new("Welcome to tooters. Sign in to your mastodon server.\nYou will be redirected to your browser to complete the authentication process.")
-            .render(welcome_area, buf);
+        let notice_height = if self.notice.is_some() { 2 } else { 0 };
+        let [welcome_area, notice_area, error_area, server_url_area, flow_area, code_area] =
+            Layout::vertical([
+                Length(3),
+                Length(notice_height),
+                Length(error_height),
+                Length(1),
+                Length(2),
+                Fill(1),
+            ])
+            .areas(area);
+        let welcome = match self.flow {
+            Flow::Browser => "Welcome to tooters. Sign in to your mastodon server.\nYou will be redirected to your browser to complete the authentication process.",
+            Flow::Manual => "Welcome to tooters. Sign in to your mastodon server.\nYou will be shown a link to open on any device, which gives you a code to paste here.",
+        };
+        Paragraph::new(welcome).render(welcome_area, buf);
+
+        if let Some(notice) = &self.notice {
+            Paragraph::new(notice.as_str())
+                .style(Style::default().fg(theme::current().warning))
+                .wrap(Wrap { trim: true })
+                .render(notice_area, buf);
+        }
 
         if let Some(error) = error {
             Paragraph::new(Line::from(vec![
                 Span::styled(
                     "Error:",
-                    Style::default().add_modifier(Modifier::BOLD).fg(Color::Red),
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .fg(theme::current().error),
                 ),
                 Span::raw(" "),
                 Span::raw(error),
@@ -325,11 +984,145 @@ impl Widget for &Authentication {
             .render(error_area, buf);
         }
 
+        let bold = Style::default().add_modifier(Modifier::BOLD);
         Paragraph::new(Line::from(vec![
-            Span::styled("Server URL:", Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled("Server URL:", bold),
             Span::raw(" "),
             Span::raw(server_url),
         ]))
         .render(server_url_area, buf);
+
+        let selected = |flow| {
+            if self.flow == flow {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            }
+        };
+        Line::from(vec![
+            Span::styled("Sign in with:", bold),
+            Span::raw(" "),
+            Span::styled(" Browser ", selected(Flow::Browser)),
+            Span::raw(" "),
+            Span::styled(" Code ", selected(Flow::Manual)),
+            Span::raw(" (Tab to switch)"),
+        ])
+        .render(flow_area, buf);
+
+        let instance = self.prompts.instance.read().expect("lock poisoned").clone();
+        let passphrase_prompt = self
+            .prompts
+            .passphrase_prompt
+            .read()
+            .expect("lock poisoned")
+            .clone();
+        if let Some(prompt) = passphrase_prompt {
+            render_passphrase_prompt(&prompt, self.passphrase_input.value(), code_area, buf);
+        } else if let Some(authorize_url) = authorize_url {
+            render_code_prompt(&authorize_url, self.code_input.value(), code_area, buf);
+        } else if let Some(instance) = instance {
+            render_instance(&instance, code_area, buf);
+        }
+    }
+}
+
+/// Renders the passphrase prompt, hiding what the user types.
+fn render_passphrase_prompt(
+    prompt: &PassphrasePrompt,
+    passphrase: &str,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let message = match prompt {
+        PassphrasePrompt::Unlock(name) => format!(
+            "The access token for {name} is encrypted. Enter your passphrase, or leave it empty to \
+             sign in again."
+        ),
+        PassphrasePrompt::Choose => "Choose a passphrase to encrypt the access token with. You \
+                                     will need it each time tooters starts. Leave it empty to \
+                                     store the token unencrypted."
+            .to_string(),
+    };
+    let masked = "*".repeat(passphrase.chars().count());
+    let text = Text::from(vec![
+        Line::styled(message, Style::default().fg(theme::current().success)),
+        Line::from(vec![
+            Span::styled("Passphrase:", bold),
+            Span::raw(" "),
+            Span::raw(masked),
+        ]),
+    ]);
+    Paragraph::new(text)
+        .wrap(Wrap { trim: true })
+        .render(area, buf);
+}
+
+/// Renders what the server says about itself, so that the user can check it before signing in.
+fn render_instance(instance: &Instance, area: Rect, buf: &mut Buffer) {
+    let theme = theme::current();
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let mut text = Text::from(vec![
+        Line::from(vec![
+            Span::styled(instance.title.clone(), bold.fg(theme.accent)),
+            Span::raw(format!(
+                " ({}, version {})",
+                instance.base, instance.version
+            )),
+        ]),
+        Line::from(vec![
+            Span::styled("Registrations:", bold),
+            Span::raw(format!(" {}", instance.registrations)),
+        ]),
+        Line::styled(
+            "Press Enter again to sign in, or change the server URL.",
+            Style::default().fg(theme.success),
+        ),
+    ]);
+    if !instance.rules.is_empty() {
+        text.push_line("");
+        text.push_line(Line::styled("Server rules:", bold));
+        for (index, rule) in instance.rules.iter().enumerate() {
+            text.push_line(format!("{}. {rule}", index + 1));
+        }
+    }
+    Paragraph::new(text)
+        .wrap(Wrap { trim: true })
+        .render(area, buf);
+}
+
+/// Renders the authorize url as text and as a QR code, along with the input for the pasted code.
+fn render_code_prompt(authorize_url: &str, code: &str, area: Rect, buf: &mut Buffer) {
+    use Constraint::{Fill, Length};
+    let url_height = u16::try_from(authorize_url.len())
+        .unwrap_or(u16::MAX)
+        .div_ceil(area.width.max(1));
+    let [instructions_area, url_area, code_area, qr_area] =
+        Layout::vertical([Length(1), Length(url_height + 1), Length(2), Fill(1)]).areas(area);
+    Paragraph::new("Open this link (or scan the QR code), authorize tooters and paste the code:")
+        .render(instructions_area, buf);
+    // no trimming so that the url can be copied from the terminal as is
+    Paragraph::new(authorize_url)
+        .style(Style::default().fg(theme::current().link))
+        .wrap(Wrap { trim: false })
+        .render(url_area, buf);
+    Paragraph::new(Line::from(vec![
+        Span::styled("Code:", Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(" "),
+        Span::raw(code.to_string()),
+    ]))
+    .render(code_area, buf);
+    // the url is always short enough to fit in a QR code
+    if let Ok(qr) = QrCode::new(authorize_url) {
+        let qr = qr.render::<Dense1x2>().build();
+        let width = qr.lines().next().map_or(0, |line| line.chars().count());
+        let qr_area = Rect {
+            width: u16::try_from(width).unwrap_or(u16::MAX).min(qr_area.width),
+            ..qr_area
+        };
+        // dark on light regardless of the terminal theme, so that phones can scan it
+        Paragraph::new(qr)
+            .style(Style::default().fg(Color::Black).bg(Color::White))
+            .render(qr_area, buf);
     }
 }
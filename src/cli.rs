@@ -0,0 +1,288 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use crossterm::event::{Event as CrosstermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use mastodon_async::data::Data;
+
+use crate::{
+    app::App,
+    authentication::{self, Flow},
+    config::{self, account_name, AccountConfig, Config},
+    crypto, instance,
+    logging::LogCollector,
+    settings::Settings,
+};
+
+mod post;
+mod timeline;
+
+/// A Rust TUI Mastodon App
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// The directory for the config, clients and settings files (defaults to ~/.config/tooters)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+    /// The account to use, e.g. `user@mastodon.social` (defaults to the current account)
+    #[arg(long, global = true)]
+    pub account: Option<String>,
+    #[command(flatten)]
+    pub overrides: Overrides,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The flags that override the settings file, also when it is reloaded.
+#[derive(Debug, Clone, Args)]
+pub struct Overrides {
+    /// The log filter, e.g. `debug` or `tooters=debug` (overrides the settings file and RUST_LOG)
+    #[arg(long, global = true, value_name = "FILTER")]
+    pub log_level: Option<String>,
+    /// Show the logs below the main view
+    #[arg(long, global = true)]
+    pub show_logs: bool,
+}
+
+#[derive(Debug, Default, Subcommand)]
+pub enum Command {
+    /// Run the app (the default)
+    #[default]
+    Tui,
+    /// Sign in to an account and make it the current account
+    Login {
+        /// The server to sign in to, e.g. `mastodon.social` or `@user@mastodon.social`
+        server: Option<String>,
+        /// Sign in with a code instead of a browser on this machine, e.g. over SSH
+        #[arg(long)]
+        code: bool,
+    },
+    /// Log out of an account, revoking its access token and removing it from the config file
+    Logout {
+        /// The account to log out of, e.g. `user@mastodon.social` (defaults to the current account)
+        account: Option<String>,
+        /// Log out of every account
+        #[arg(long, conflicts_with = "account")]
+        all: bool,
+    },
+    /// List the accounts, marking the current account with `*`
+    Accounts,
+    /// Print a timeline as text, JSON or NDJSON
+    Timeline(timeline::TimelineArgs),
+    /// Post a status, printing its url
+    Post(post::PostArgs),
+}
+
+impl Overrides {
+    /// Applies the flags to the settings.
+    pub fn apply(&self, settings: &mut Settings) -> Result<()> {
+        if let Some(level) = &self.log_level {
+            settings.logging.level.clone_from(level);
+            settings.logging.filter().wrap_err("invalid --log-level")?;
+        }
+        settings.logging.show |= self.show_logs;
+        Ok(())
+    }
+}
+
+impl Cli {
+    /// Points the config files at `--config-dir`, if given. This must happen before any are
+    /// loaded.
+    pub fn use_config_dir(&self) {
+        if let Some(dir) = &self.config_dir {
+            config::set_config_dir(dir.clone());
+        }
+    }
+
+    pub async fn run(self, settings: Settings, logs: LogCollector) -> Result<()> {
+        match self.command.unwrap_or_default() {
+            Command::Tui => tui(logs, settings, self.account, self.overrides).await,
+            Command::Login { server, code } => login(server, code, &settings).await,
+            Command::Logout { account, all } => logout(account.or(self.account), all).await,
+            Command::Accounts => accounts(),
+            Command::Timeline(args) => timeline::run(args, self.account, &settings).await,
+            Command::Post(args) => post::run(args, self.account).await,
+        }
+    }
+}
+
+async fn tui(
+    logs: LogCollector,
+    settings: Settings,
+    account: Option<String>,
+    overrides: Overrides,
+) -> Result<()> {
+    let terminal = ratatui::init();
+    let result = App::new(logs, settings, account, overrides)
+        .run(terminal)
+        .await;
+    ratatui::restore();
+    result
+}
+
+async fn login(server: Option<String>, code: bool, settings: &Settings) -> Result<()> {
+    let server = match server {
+        Some(server) => server,
+        None => read_line("Server URL: ")?,
+    };
+    let server_url = instance::normalize_server_url(&server)?;
+    let instance = instance::probe(&server_url).await?;
+    println!(
+        "Signing in to {} ({}, version {})",
+        instance.title, instance.base, instance.version
+    );
+    let flow = if code {
+        Flow::Manual
+    } else {
+        println!("Authorize tooters in the browser window that opens");
+        Flow::Browser
+    };
+    let (mastodon, scopes) = authentication::authorize_at(
+        &server_url,
+        flow,
+        &settings.auth,
+        settings.auth.scopes()?,
+        |auth_url| async move {
+            println!("Open this link on any device to authorize tooters:\n{auth_url}");
+            read_line("Code: ")
+        },
+    )
+    .await?;
+    let account = mastodon
+        .verify_credentials()
+        .await
+        .wrap_err("failed to verify credentials")?;
+    let name = account_name(&account.username, &mastodon.data.base);
+    let encrypted_token = if settings.auth.encrypt_tokens {
+        let passphrase = read_hidden("Passphrase to encrypt the access token with: ")?;
+        if passphrase.is_empty() {
+            bail!("no passphrase entered");
+        }
+        Some(crypto::encrypt(&passphrase, &mastodon.data.token)?)
+    } else {
+        None
+    };
+    let mut config = Config::load()?;
+    let saved = AccountConfig::new(mastodon.data.clone(), Some(scopes), encrypted_token);
+    config.add_account(name.clone(), saved);
+    config.save()?;
+    println!("Logged in as {name}");
+    Ok(())
+}
+
+async fn logout(account: Option<String>, all: bool) -> Result<()> {
+    let config = Config::load()?;
+    let names: Vec<String> = if all {
+        config.accounts.keys().cloned().collect()
+    } else {
+        let name = account
+            .or_else(|| config.current_account().map(|(name, _)| name.clone()))
+            .ok_or_else(|| eyre!("not logged in to any account"))?;
+        vec![name]
+    };
+    let mut unrevoked = 0;
+    for name in names {
+        let passphrase = match config.accounts.get(&name) {
+            Some(saved) if saved.encrypted_token.is_some() => {
+                Some(read_hidden(&format!("Passphrase for {name}: "))?)
+            }
+            _ => None,
+        };
+        let revoke_error = authentication::log_out(&name, passphrase.as_deref()).await?;
+        println!("Logged out of {name}");
+        if let Some(err) = revoke_error {
+            eprintln!("Unable to revoke the access token of {name}: {err:#}");
+            unrevoked += 1;
+        }
+    }
+    if unrevoked > 0 {
+        bail!(
+            "{unrevoked} access token(s) were not revoked and stay valid until revoked in the \
+             server's settings"
+        );
+    }
+    Ok(())
+}
+
+fn accounts() -> Result<()> {
+    let config = Config::load()?;
+    let current = config.current_account().map(|(name, _)| name.clone());
+    for name in config.accounts.keys() {
+        let marker = if current.as_ref() == Some(name) {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{marker} {name}");
+    }
+    Ok(())
+}
+
+/// The name and credentials of the named account, or else of the current account. Asks for the
+/// passphrase if the access token is encrypted.
+fn saved_credentials(account: Option<&str>) -> Result<(String, Data)> {
+    let config = Config::load()?;
+    let (name, saved) = match account {
+        Some(name) => config
+            .accounts
+            .get_key_value(name)
+            .ok_or_else(|| eyre!("no account named {name}"))?,
+        None => config
+            .current_account()
+            .ok_or_else(|| eyre!("not logged in to any account, run `tooters login` first"))?,
+    };
+    let passphrase = match saved.encrypted_token {
+        Some(_) => Some(read_hidden(&format!("Passphrase for {name}: "))?),
+        None => None,
+    };
+    let data = saved.decrypted_data(passphrase.as_deref())?;
+    Ok((name.clone(), data))
+}
+
+/// Reads a line from stdin after showing `prompt`. Prompts go to stderr, so that they don't end up
+/// in piped output.
+fn read_line(prompt: &str) -> Result<String> {
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Reads a line from the terminal after showing `prompt`, without echoing what the user types.
+fn read_hidden(prompt: &str) -> Result<String> {
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let result = read_hidden_line();
+    crossterm::terminal::disable_raw_mode()?;
+    eprintln!();
+    result
+}
+
+fn read_hidden_line() -> Result<String> {
+    let mut line = String::new();
+    loop {
+        let CrosstermEvent::Key(key) = crossterm::event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match (key.modifiers, key.code) {
+            (_, KeyCode::Enter) => return Ok(line),
+            (_, KeyCode::Backspace) => {
+                line.pop();
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('c')) => bail!("cancelled"),
+            (_, KeyCode::Char(c)) => line.push(c),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! `tooters post`: posts a status from scripts, e.g. to announce a deploy.
+
+use std::{
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use megalodon::{
+    entities::{status::StatusVisibility, UploadMedia},
+    megalodon::{PostStatusInputOptions, PostStatusOutput, UploadMediaInputOptions},
+    Megalodon,
+};
+use tracing::info;
+
+use super::saved_credentials;
+use crate::authentication;
+
+/// How long to wait for the server to finish processing uploaded media
+const MEDIA_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Args)]
+pub struct PostArgs {
+    /// The text of the status. Read from stdin if missing or `-`
+    text: Option<String>,
+    /// Who can see the status (defaults to the account's default visibility)
+    #[arg(long, value_enum)]
+    visibility: Option<Visibility>,
+    /// A content warning, shown instead of the text until the reader expands it
+    #[arg(long, visible_alias = "cw", value_name = "TEXT")]
+    spoiler: Option<String>,
+    /// Mark the attached media as sensitive
+    #[arg(long)]
+    sensitive: bool,
+    /// The id of the status to reply to
+    #[arg(long, value_name = "ID")]
+    reply_to: Option<String>,
+    /// The language of the status as an ISO 639 code, e.g. `en`
+    #[arg(long, value_name = "CODE")]
+    language: Option<String>,
+    /// A file to attach. Repeat to attach several files
+    #[arg(long, value_name = "PATH")]
+    media: Vec<PathBuf>,
+    /// The alt text of the attached files, in the same order as --media
+    #[arg(long, value_name = "TEXT")]
+    alt: Vec<String>,
+    /// Post the status at this time instead of now, e.g. `2024-06-01T09:00:00Z`
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    schedule: Option<DateTime<Utc>>,
+    /// Print the new status as JSON instead of its url
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Visibility {
+    /// Everyone, in public timelines
+    Public,
+    /// Everyone, but not in public timelines
+    Unlisted,
+    /// Followers only
+    Private,
+    /// Mentioned accounts only
+    Direct,
+}
+
+impl From<Visibility> for StatusVisibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Public => Self::Public,
+            Visibility::Unlisted => Self::Unlisted,
+            Visibility::Private => Self::Private,
+            Visibility::Direct => Self::Direct,
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(time).map(|time| time.with_timezone(&Utc))
+}
+
+/// Posts the status and prints its url (or the scheduled status id), or the JSON of either.
+pub async fn run(args: PostArgs, account: Option<String>) -> Result<()> {
+    if args.alt.len() > args.media.len() {
+        bail!("there is more --alt text than --media files");
+    }
+    let text = match args.text.as_deref() {
+        None | Some("-") => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .wrap_err("unable to read the status from stdin")?;
+            text.trim_end().to_string()
+        }
+        Some(text) => text.to_string(),
+    };
+    if text.trim().is_empty() && args.media.is_empty() {
+        bail!("status is empty");
+    }
+    let (name, data) = saved_credentials(account.as_deref())?;
+    let client = authentication::client(&data)?;
+    let mut media_ids = vec![];
+    for (index, path) in args.media.iter().enumerate() {
+        let alt = args.alt.get(index).cloned();
+        media_ids.push(upload(&*client, path, alt).await?);
+    }
+    let options = PostStatusInputOptions {
+        media_ids: Some(media_ids).filter(|ids| !ids.is_empty()),
+        in_reply_to_id: args.reply_to,
+        sensitive: Some(args.sensitive),
+        spoiler_text: args.spoiler.filter(|text| !text.is_empty()),
+        visibility: args.visibility.map(StatusVisibility::from),
+        scheduled_at: args.schedule,
+        language: args.language,
+        ..Default::default()
+    };
+    info!(name, "Posting status");
+    let output = client
+        .post_status(text, Some(&options))
+        .await
+        .wrap_err("failed to post status")?
+        .json;
+    match output {
+        PostStatusOutput::Status(status) if args.json => {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        }
+        PostStatusOutput::Status(status) => {
+            println!("{}", status.url.unwrap_or(status.uri));
+        }
+        PostStatusOutput::ScheduledStatus(scheduled) if args.json => {
+            println!("{}", serde_json::to_string_pretty(&scheduled)?);
+        }
+        PostStatusOutput::ScheduledStatus(scheduled) => {
+            println!(
+                "Scheduled status {} for {}",
+                scheduled.id, scheduled.scheduled_at
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Uploads a file and returns its media id once the server has processed it, as statuses can't
+/// be posted with media that is still processing.
+async fn upload(
+    client: &(dyn Megalodon + Send + Sync),
+    path: &Path,
+    description: Option<String>,
+) -> Result<String> {
+    let options = UploadMediaInputOptions {
+        description,
+        focus: None,
+    };
+    info!(path = %path.display(), "Uploading media");
+    let uploaded = client
+        .upload_media(path.display().to_string(), Some(&options))
+        .await
+        .wrap_err_with(|| format!("failed to upload {}", path.display()))?
+        .json;
+    let id = match uploaded {
+        UploadMedia::Attachment(attachment) => return Ok(attachment.id),
+        UploadMedia::AsyncAttachment(attachment) => attachment.id,
+    };
+    let processed = async {
+        loop {
+            match client.get_media(id.clone()).await {
+                Ok(_) => return Ok(()),
+                Err(err) if is_processing(&err) => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    };
+    tokio::time::timeout(MEDIA_TIMEOUT, processed)
+        .await
+        .map_err(|_| eyre!("the server did not finish processing {}", path.display()))?
+        .wrap_err_with(|| format!("failed to check on {}", path.display()))?;
+    Ok(id)
+}
+
+/// Whether `err` means that uploaded media is still being processed: the server responds with 206
+/// Partial Content, or with an attachment that has no url yet, which fails to parse.
+fn is_processing(err: &megalodon::error::Error) -> bool {
+    match err {
+        megalodon::error::Error::OwnError(err) => err.status == Some(206),
+        megalodon::error::Error::RequestError(err) => err.is_decode(),
+        megalodon::error::Error::JsonError(_) => true,
+        _ => false,
+    }
+}
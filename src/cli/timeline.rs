@@ -0,0 +1,278 @@
+//! `tooters timeline`: prints a timeline for scripts and other tools.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
+use chrono::Utc;
+use clap::{Args, ValueEnum};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use megalodon::{
+    entities::Status,
+    megalodon::{
+        GetHomeTimelineInputOptions, GetListTimelineInputOptions, GetLocalTimelineInputOptions,
+        GetPublicTimelineInputOptions, GetTagTimelineInputOptions,
+    },
+    Megalodon,
+};
+
+use super::saved_credentials;
+use crate::{authentication, home, settings::Settings};
+
+/// The width of plain text output when stdout is not a terminal
+const DEFAULT_WIDTH: u16 = 80;
+
+#[derive(Debug, Args)]
+pub struct TimelineArgs {
+    /// The timeline to print
+    #[arg(value_enum, default_value_t)]
+    timeline: Timeline,
+    /// Print the statuses with this hashtag instead
+    #[arg(long, value_name = "HASHTAG", conflicts_with = "list")]
+    tag: Option<String>,
+    /// Print the statuses in the list with this id instead
+    #[arg(long, value_name = "ID")]
+    list: Option<String>,
+    /// How many statuses to print, at most 40 (defaults to the timelines.limit setting). With
+    /// --since, every newer status is printed, this many at a time
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=40))]
+    limit: Option<u32>,
+    /// Only print statuses newer than the status with this id
+    #[arg(long, value_name = "ID")]
+    since: Option<String>,
+    /// Keep printing new statuses as they are posted
+    #[arg(long)]
+    follow: bool,
+    /// How often to check for new statuses with --follow, in seconds
+    #[arg(long, default_value_t = 30, requires = "follow")]
+    interval: u64,
+    /// How to print the statuses
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum Timeline {
+    /// The statuses of the accounts that you follow
+    #[default]
+    Home,
+    /// The statuses posted on your server
+    Local,
+    /// The statuses that your server knows about
+    Public,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Formatted like in the app, without colors
+    #[default]
+    Text,
+    /// A JSON array of statuses
+    Json,
+    /// One JSON status per line
+    Ndjson,
+}
+
+/// The timeline to fetch, once the flags are resolved.
+enum Source {
+    Timeline(Timeline),
+    Tag(String),
+    List(String),
+}
+
+/// Which statuses to fetch: the newest ones, or those right after a status.
+struct Page {
+    limit: Option<u32>,
+    min_id: Option<String>,
+}
+
+/// Prints the timeline, oldest status first, and then new statuses as they arrive with --follow.
+pub async fn run(args: TimelineArgs, account: Option<String>, settings: &Settings) -> Result<()> {
+    if args.follow && args.format == Format::Json {
+        bail!("--follow prints statuses as they arrive, which needs --format ndjson or text");
+    }
+    let source = match (args.tag, args.list) {
+        (Some(tag), _) => Source::Tag(tag.trim_start_matches('#').to_string()),
+        (None, Some(list)) => Source::List(list),
+        (None, None) => Source::Timeline(args.timeline),
+    };
+    let (_, data) = saved_credentials(account.as_deref())?;
+    let client = authentication::client(&data)?;
+    let limit = args.limit.unwrap_or(settings.timelines.limit);
+    let statuses = if let Some(since) = args.since {
+        fetch_after(&*client, &source, limit, since).await?
+    } else {
+        let page = Page {
+            limit: Some(limit),
+            min_id: None,
+        };
+        let mut statuses = fetch(&*client, &source, page).await?;
+        statuses.reverse();
+        statuses
+    };
+    let output = Output {
+        format: args.format,
+        strip_emojis: settings.ui.screen_reader,
+    };
+    output.print(&statuses)?;
+    if !args.follow {
+        return Ok(());
+    }
+    let mut newest = statuses.last().map(|status| status.id.clone());
+    loop {
+        tokio::time::sleep(Duration::from_secs(args.interval)).await;
+        let statuses = match &newest {
+            Some(newest) => fetch_after(&*client, &source, limit, newest.clone()).await,
+            None => {
+                let page = Page {
+                    limit: Some(limit),
+                    min_id: None,
+                };
+                fetch(&*client, &source, page).await.map(|mut statuses| {
+                    statuses.reverse();
+                    statuses
+                })
+            }
+        };
+        let statuses = match statuses {
+            Ok(statuses) => statuses,
+            Err(err) if authentication::is_unauthorized(&err) => return Err(err),
+            // keep following through network errors and server hiccups
+            Err(err) => {
+                eprintln!("{err:#}, retrying in {}s", args.interval);
+                continue;
+            }
+        };
+        if let Some(status) = statuses.last() {
+            newest = Some(status.id.clone());
+        }
+        output.print(&statuses)?;
+    }
+}
+
+/// Fetches every status after the status with id `min_id`, oldest first, `limit` at a time.
+///
+/// `min_id` returns the statuses right after it, where `since_id` would return the newest ones and
+/// skip those in between.
+async fn fetch_after(
+    client: &(dyn Megalodon + Send + Sync),
+    source: &Source,
+    limit: u32,
+    min_id: String,
+) -> Result<Vec<Status>> {
+    let mut statuses = vec![];
+    let mut min_id = min_id;
+    loop {
+        let page = Page {
+            limit: Some(limit),
+            min_id: Some(min_id),
+        };
+        let mut page = fetch(client, source, page).await?;
+        page.reverse();
+        let Some(newest) = page.last() else {
+            return Ok(statuses);
+        };
+        min_id = newest.id.clone();
+        let caught_up = page.len() < usize::try_from(limit).unwrap_or(usize::MAX);
+        statuses.extend(page);
+        if caught_up {
+            return Ok(statuses);
+        }
+    }
+}
+
+/// Fetches a page of the timeline, newest status first.
+async fn fetch(
+    client: &(dyn Megalodon + Send + Sync),
+    source: &Source,
+    page: Page,
+) -> Result<Vec<Status>> {
+    let Page { limit, min_id } = page;
+    let response = match source {
+        Source::Timeline(Timeline::Home) => {
+            let options = GetHomeTimelineInputOptions {
+                limit,
+                min_id,
+                ..Default::default()
+            };
+            client.get_home_timeline(Some(&options)).await
+        }
+        Source::Timeline(Timeline::Local) => {
+            let options = GetLocalTimelineInputOptions {
+                limit,
+                min_id,
+                ..Default::default()
+            };
+            client.get_local_timeline(Some(&options)).await
+        }
+        Source::Timeline(Timeline::Public) => {
+            let options = GetPublicTimelineInputOptions {
+                limit,
+                min_id,
+                ..Default::default()
+            };
+            client.get_public_timeline(Some(&options)).await
+        }
+        Source::Tag(tag) => {
+            let options = GetTagTimelineInputOptions {
+                limit,
+                min_id,
+                ..Default::default()
+            };
+            client.get_tag_timeline(tag.clone(), Some(&options)).await
+        }
+        Source::List(id) => {
+            let options = GetListTimelineInputOptions {
+                limit,
+                min_id,
+                ..Default::default()
+            };
+            client.get_list_timeline(id.clone(), Some(&options)).await
+        }
+    };
+    Ok(response.wrap_err("failed to load timeline")?.json)
+}
+
+struct Output {
+    format: Format,
+    strip_emojis: bool,
+}
+
+impl Output {
+    fn print(&self, statuses: &[Status]) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        match self.format {
+            Format::Text => {
+                let width = if stdout.is_terminal() {
+                    crossterm::terminal::size().map_or(DEFAULT_WIDTH, |(width, _)| width)
+                } else {
+                    DEFAULT_WIDTH
+                };
+                let now = Utc::now();
+                for status in statuses {
+                    let text = home::format_status(status, width, now, self.strip_emojis);
+                    for line in text.lines {
+                        let line: String = line.spans.iter().map(|span| &*span.content).collect();
+                        writeln!(stdout, "{}", line.trim_end())?;
+                    }
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut stdout, statuses)?;
+                writeln!(stdout)?;
+            }
+            Format::Ndjson => {
+                for status in statuses {
+                    serde_json::to_writer(&mut stdout, status)?;
+                    writeln!(stdout)?;
+                }
+            }
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! The commands of the `:` command line and the command palette.
+//!
+//! Every action from the keymap is also a command with the same name, e.g. `:compose`, so the
+//! command line runs the same actions as the keys do. The other commands take an argument.
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+use crate::{
+    home::Timeline,
+    keymap::{Action, Context},
+};
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run an action from the keymap
+    Action(Action),
+    /// Show a timeline in the home view
+    Timeline(Timeline),
+    /// Open a url in the browser
+    Open(String),
+    /// Switch to the account with this name, or the only one that starts with it
+    Account(String),
+    /// Change the log filter until the settings file changes or the app restarts
+    LogLevel(String),
+    /// Change the theme until the settings file changes or the app restarts
+    Theme(String),
+}
+
+/// A command as listed in the palette and completed on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spec {
+    pub name: &'static str,
+    /// What the argument is, for commands that take one
+    pub argument: Option<&'static str>,
+    pub description: &'static str,
+}
+
+/// The commands that aren't actions from the keymap.
+const COMMANDS: &[Spec] = &[
+    Spec {
+        name: "home",
+        argument: None,
+        description: "Show the home timeline",
+    },
+    Spec {
+        name: "tag",
+        argument: Some("hashtag"),
+        description: "Show the statuses with a hashtag",
+    },
+    Spec {
+        name: "list",
+        argument: Some("title"),
+        description: "Show the statuses in one of your lists",
+    },
+    Spec {
+        name: "user",
+        argument: Some("@user@server"),
+        description: "Show the statuses of an account",
+    },
+    Spec {
+        name: "open",
+        argument: Some("url"),
+        description: "Open a url in the browser",
+    },
+    Spec {
+        name: "account",
+        argument: Some("name"),
+        description: "Switch to another account",
+    },
+    Spec {
+        name: "loglevel",
+        argument: Some("filter"),
+        description: "Change the log filter, e.g. debug",
+    },
+    Spec {
+        name: "theme",
+        argument: Some("name"),
+        description: "Change the theme, e.g. light",
+    },
+];
+
+/// The views whose actions can be run as commands, which are those that the command line opens
+/// over.
+const ACTION_CONTEXTS: &[Context] = &[
+    Context::Global,
+    Context::Home,
+    Context::History,
+    Context::Accounts,
+];
+
+/// The log levels, for completing `:loglevel`.
+pub const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+/// Every command: those with arguments first, then the actions.
+pub fn specs() -> Vec<Spec> {
+    let mut actions: Vec<Action> = ACTION_CONTEXTS
+        .iter()
+        .flat_map(|context| context.actions())
+        .copied()
+        .filter(|action| !matches!(action, Action::CommandLine | Action::Palette))
+        .collect();
+    actions.sort();
+    actions.dedup();
+    let actions = actions.into_iter().map(|action| Spec {
+        name: action.name(),
+        argument: None,
+        description: action.description(),
+    });
+    COMMANDS.iter().copied().chain(actions).collect()
+}
+
+impl std::str::FromStr for Command {
+    type Err = color_eyre::Report;
+
+    /// Parses a command line such as `tag rust`, without the `:`.
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim().trim_start_matches(':');
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+        let spec = specs()
+            .into_iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| eyre!("unknown command: {name}"))?;
+        if let Some(expected) = spec.argument {
+            if argument.is_empty() {
+                bail!("usage: :{name} <{expected}>");
+            }
+        } else if !argument.is_empty() {
+            bail!(":{name} takes no argument");
+        }
+        let argument = argument.to_string();
+        let command = match name {
+            "home" => Self::Timeline(Timeline::Home),
+            "tag" => Self::Timeline(Timeline::Tag(argument.trim_start_matches('#').to_string())),
+            "list" => Self::Timeline(Timeline::List(argument)),
+            "user" => Self::Timeline(Timeline::User(argument.trim_start_matches('@').to_string())),
+            "open" => Self::Open(argument),
+            "account" => Self::Account(argument),
+            "loglevel" => Self::LogLevel(argument),
+            "theme" => Self::Theme(argument),
+            _ => {
+                let action = ACTION_CONTEXTS
+                    .iter()
+                    .flat_map(|context| context.actions())
+                    .find(|action| action.name() == name)
+                    .expect("every other spec is an action");
+                Self::Action(*action)
+            }
+        };
+        Ok(command)
+    }
+}
+
+/// Scores how well `query` fuzzy matches `text`: every character of the query must appear in order.
+/// Higher scores are better matches, favouring consecutive characters and matches at the start of
+/// words.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for query_char in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let index = position + text[position..].iter().position(|c| *c == query_char)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 4;
+        }
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += 2;
+        }
+        previous = Some(index);
+        position = index + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            "tag #rust".parse::<Command>().unwrap(),
+            Command::Timeline(Timeline::Tag("rust".to_string()))
+        );
+        assert_eq!(
+            ":user @foo@bar.social".parse::<Command>().unwrap(),
+            Command::Timeline(Timeline::User("foo@bar.social".to_string()))
+        );
+        assert_eq!(
+            "list  work stuff ".parse::<Command>().unwrap(),
+            Command::Timeline(Timeline::List("work stuff".to_string()))
+        );
+        assert_eq!(
+            "compose".parse::<Command>().unwrap(),
+            Command::Action(Action::Compose)
+        );
+        assert!("tag".parse::<Command>().is_err());
+        assert!("compose now".parse::<Command>().is_err());
+        assert!("frobnicate".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn fuzzy_matches_in_order() {
+        assert!(fuzzy_score("sh", "show_history").is_some());
+        assert!(fuzzy_score("hs", "show_history").is_some());
+        assert!(fuzzy_score("xyz", "show_history").is_none());
+        assert!(fuzzy_score("tag", "tag") > fuzzy_score("tag", "the account guide"));
+    }
+}
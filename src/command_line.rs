@@ -0,0 +1,357 @@
+use std::path::PathBuf;
+
+use color_eyre::{eyre::WrapErr, Result};
+use crossterm::event::Event as CrosstermEvent;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+use tracing::warn;
+use tui_input::{backend::crossterm::EventHandler, Input};
+use xdg::BaseDirectories;
+
+use crate::{
+    command::{self, Spec, LOG_LEVELS},
+    config::Config,
+    theme,
+};
+
+/// The most command lines to remember across sessions
+const HISTORY_LIMIT: usize = 100;
+
+/// The `:` command line at the bottom of the view, and the command palette, which finds commands
+/// by fuzzy search. Both remember the commands that were run, also across sessions.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    mode: Option<Mode>,
+    input: Input,
+    /// The commands that were run, oldest first
+    history: Vec<String>,
+    /// The command from the history that is shown, while browsing it
+    history_index: Option<usize>,
+    /// The candidates of an ambiguous completion, shown above the command line
+    completions: Vec<String>,
+    /// The selected command in the palette
+    selected: usize,
+    /// The saved accounts, for completing `:account`
+    accounts: Vec<String>,
+    /// The presets and the user's themes, for completing `:theme`
+    themes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Line,
+    Palette,
+}
+
+impl CommandLine {
+    const PALETTE_WIDTH: u16 = 72;
+    const PALETTE_HEIGHT: u16 = 16;
+
+    pub fn new() -> Self {
+        Self {
+            history: load_history().unwrap_or_else(|err| {
+                warn!("Unable to load the command history: {err:#}");
+                vec![]
+            }),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_themes(&mut self, themes: Vec<String>) {
+        self.themes = themes;
+    }
+
+    pub const fn is_open(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    pub fn open(&mut self, mode: Mode) {
+        self.mode = Some(mode);
+        self.input.reset();
+        self.history_index = None;
+        self.completions.clear();
+        self.selected = 0;
+        self.accounts = Config::load()
+            .map(|config| config.accounts.into_keys().collect())
+            .unwrap_or_default();
+    }
+
+    pub fn close(&mut self) {
+        self.mode = None;
+    }
+
+    /// Types into the command line.
+    pub fn handle_event(&mut self, event: &CrosstermEvent) {
+        if self.input.handle_event(event).is_some() {
+            self.completions.clear();
+            self.selected = 0;
+        }
+    }
+
+    /// Closes the command line and returns the command to run, which is remembered in the history.
+    /// In the palette, this is the selected command, or else the command line is opened with the
+    /// name of the command filled in so that the user can type its argument.
+    pub fn submit(&mut self) -> Option<String> {
+        let line = match self.mode? {
+            Mode::Line => self.input.value().trim().to_string(),
+            Mode::Palette => {
+                let spec = *self.palette_matches().get(self.selected)?;
+                if spec.argument.is_some() {
+                    self.open(Mode::Line);
+                    self.input = Input::new(format!("{} ", spec.name));
+                    return None;
+                }
+                spec.name.to_string()
+            }
+        };
+        self.close();
+        if line.is_empty() {
+            return None;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        let start = self.history.len().saturating_sub(HISTORY_LIMIT);
+        self.history.drain(..start);
+        if let Err(err) = save_history(&self.history) {
+            warn!("Unable to save the command history: {err:#}");
+        }
+        Some(line)
+    }
+
+    /// Moves to an older command in the history, or up the palette.
+    pub fn up(&mut self, count: usize) {
+        match self.mode {
+            Some(Mode::Line) if !self.history.is_empty() => {
+                let index = self
+                    .history_index
+                    .unwrap_or(self.history.len())
+                    .saturating_sub(count);
+                self.show_history(Some(index));
+            }
+            Some(Mode::Palette) => self.selected = self.selected.saturating_sub(count),
+            _ => {}
+        }
+    }
+
+    /// Moves to a newer command in the history, or down the palette.
+    pub fn down(&mut self, count: usize) {
+        match self.mode {
+            Some(Mode::Line) => {
+                let Some(index) = self.history_index else {
+                    return;
+                };
+                let index = index.saturating_add(count);
+                self.show_history((index < self.history.len()).then_some(index));
+            }
+            Some(Mode::Palette) => {
+                let last = self.palette_matches().len().saturating_sub(1);
+                self.selected = self.selected.saturating_add(count).min(last);
+            }
+            None => {}
+        }
+    }
+
+    /// Shows a command from the history, or an empty line past the newest command.
+    fn show_history(&mut self, index: Option<usize>) {
+        self.history_index = index;
+        let line = index.map_or_else(String::new, |index| self.history[index].clone());
+        self.input = Input::new(line);
+    }
+
+    /// Completes the command name, or the argument of commands whose arguments are known. An
+    /// ambiguous completion is completed as far as the candidates agree, and they are listed.
+    pub fn complete(&mut self) {
+        if self.mode == Some(Mode::Palette) {
+            if let Some(spec) = self.palette_matches().get(self.selected) {
+                self.input = Input::new(spec.name.to_string());
+            }
+            return;
+        }
+        let line = self.input.value().to_string();
+        let (prefix, word, candidates): (String, &str, Vec<String>) =
+            match line.split_once(char::is_whitespace) {
+                None => {
+                    let names = command::specs()
+                        .into_iter()
+                        .map(|spec| spec.name.to_string());
+                    (String::new(), &line, names.collect())
+                }
+                Some((name, argument)) => {
+                    let candidates = match name {
+                        "account" => self.accounts.clone(),
+                        "loglevel" => LOG_LEVELS.iter().map(ToString::to_string).collect(),
+                        "theme" => self.themes.clone(),
+                        _ => vec![],
+                    };
+                    (format!("{name} "), argument.trim_start(), candidates)
+                }
+            };
+        let candidates: Vec<String> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        let completed = match candidates.as_slice() {
+            [] => return,
+            [candidate] if prefix.is_empty() => format!("{candidate} "),
+            [candidate] => candidate.clone(),
+            [first, rest @ ..] => rest.iter().fold(first.clone(), |common, candidate| {
+                common
+                    .chars()
+                    .zip(candidate.chars())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a)
+                    .collect()
+            }),
+        };
+        self.completions = if candidates.len() > 1 {
+            candidates
+        } else {
+            vec![]
+        };
+        self.input = Input::new(format!("{prefix}{completed}"));
+    }
+
+    /// The commands that match the palette search, best match first.
+    fn palette_matches(&self) -> Vec<Spec> {
+        let mut matches: Vec<(usize, Spec)> = command::specs()
+            .into_iter()
+            .filter_map(|spec| {
+                let text = format!("{} {}", spec.name, spec.description);
+                command::fuzzy_score(self.input.value(), &text).map(|score| (score, spec))
+            })
+            .collect();
+        // the sort is stable, so equally good matches keep their order
+        matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+        matches.into_iter().map(|(_, spec)| spec).collect()
+    }
+
+    fn render_line(&self, area: Rect, buf: &mut Buffer) {
+        let completions_height = u16::from(!self.completions.is_empty());
+        let height = (1 + completions_height).min(area.height);
+        let area = Rect {
+            y: area.bottom() - height,
+            height,
+            ..area
+        };
+        Clear.render(area, buf);
+        let [completions, line] = Layout::vertical([
+            Constraint::Length(completions_height),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let gray = Style::default().fg(theme::current().muted);
+        Line::styled(self.completions.join("  "), gray).render(completions, buf);
+        let value = self.input.value();
+        let (before, after) = value.split_at(
+            value
+                .char_indices()
+                .nth(self.input.cursor())
+                .map_or(value.len(), |(index, _)| index),
+        );
+        let mut after = after.chars();
+        let cursor = after.next().map_or_else(|| " ".to_string(), String::from);
+        Line::from(vec![
+            Span::raw(":"),
+            Span::raw(before),
+            Span::styled(cursor, Style::default().add_modifier(Modifier::REVERSED)),
+            Span::raw(after.as_str()),
+        ])
+        .render(line, buf);
+    }
+
+    fn render_palette(&self, area: Rect, buf: &mut Buffer) {
+        let [area] = Layout::horizontal([Constraint::Length(Self::PALETTE_WIDTH)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(Self::PALETTE_HEIGHT)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        let block = Block::bordered().title(" Commands ");
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let [search, _, list] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+        let gray = Style::default().fg(theme::current().muted);
+        if self.input.value().is_empty() {
+            Line::styled("Type to search", gray).render(search, buf);
+        } else {
+            Line::from(vec![
+                Span::styled("Search: ", gray),
+                Span::raw(self.input.value()),
+            ])
+            .render(search, buf);
+        }
+        let visible = usize::from(list.height);
+        let first = (self.selected + 1).saturating_sub(visible);
+        let lines: Vec<Line> = self
+            .palette_matches()
+            .into_iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(index, spec)| {
+                let name = spec.argument.map_or_else(
+                    || spec.name.to_string(),
+                    |argument| format!("{} <{argument}>", spec.name),
+                );
+                let style = if index == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(format!("{name:24}"), style.add_modifier(Modifier::BOLD)),
+                    Span::styled(format!(" {}", spec.description), style),
+                ])
+            })
+            .collect();
+        Paragraph::new(lines).render(list, buf);
+    }
+}
+
+impl Widget for &CommandLine {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        match self.mode {
+            Some(Mode::Line) => self.render_line(area, buf),
+            Some(Mode::Palette) => self.render_palette(area, buf),
+            None => {}
+        }
+    }
+}
+
+/// The file that keeps the command history across sessions, usually
+/// ~/.local/state/tooters/command_history
+fn history_file() -> Result<PathBuf> {
+    BaseDirectories::with_prefix("tooters")
+        .wrap_err("failed to get XDG base directories")?
+        .place_state_file("command_history")
+        .wrap_err("failed to create the state directory")
+}
+
+fn load_history() -> Result<Vec<String>> {
+    let path = history_file()?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("unable to read {}", path.display()))?;
+    Ok(contents.lines().map(ToString::to_string).collect())
+}
+
+fn save_history(history: &[String]) -> Result<()> {
+    let path = history_file()?;
+    let mut contents = history.join("\n");
+    contents.push('\n');
+    std::fs::write(&path, contents).wrap_err_with(|| format!("unable to write {}", path.display()))
+}
@@ -0,0 +1,517 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::bail, Result};
+use crossterm::event::{Event as CrosstermEvent, KeyCode};
+use megalodon::{
+    entities::{status::StatusVisibility, Poll, Status, StatusSource},
+    megalodon::{EditStatusInputOptions, PollOptions, PostStatusInputOptions, PostStatusOutput},
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info};
+use tui_input::{backend::crossterm::EventHandler, Input};
+use tui_textarea::TextArea;
+
+use crate::{
+    authentication,
+    event::{Event, Outcome},
+    keymap::Action,
+    theme,
+};
+
+/// The contents of a status being written, either a new status or an edit of an existing one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Draft {
+    /// The id of the status being edited, or `None` for a new status
+    pub edit_id: Option<String>,
+    pub in_reply_to_id: Option<String>,
+    pub text: String,
+    pub spoiler_text: String,
+    pub sensitive: bool,
+    pub visibility: StatusVisibility,
+    pub language: Option<String>,
+    /// Already uploaded media, kept when editing or redrafting a status
+    pub media_ids: Vec<String>,
+    /// The poll of the status. The composer can't change it, so it is only sent for new statuses
+    pub poll: Option<DraftPoll>,
+    /// The status was deleted to redraft it, so leaving without sending loses it
+    pub deleted: bool,
+}
+
+/// The poll of a status being edited or redrafted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DraftPoll {
+    pub options: Vec<String>,
+    pub expires_in: Option<u64>,
+    pub multiple: bool,
+}
+
+impl Default for Draft {
+    fn default() -> Self {
+        Self {
+            edit_id: None,
+            in_reply_to_id: None,
+            text: String::new(),
+            spoiler_text: String::new(),
+            sensitive: false,
+            visibility: StatusVisibility::Public,
+            language: None,
+            media_ids: vec![],
+            poll: None,
+            deleted: false,
+        }
+    }
+}
+
+impl Draft {
+    /// A draft for editing `status`, using the plain text `source` fetched from the server.
+    pub fn edit(status: &Status, source: StatusSource) -> Self {
+        Self {
+            edit_id: Some(status.id.clone()),
+            deleted: false,
+            ..Self::redraft(status, source)
+        }
+    }
+
+    /// A draft for a new status with the contents of a deleted `status` (delete and redraft).
+    pub fn redraft(status: &Status, source: StatusSource) -> Self {
+        Self {
+            edit_id: None,
+            in_reply_to_id: status.in_reply_to_id.clone(),
+            text: source.text,
+            spoiler_text: source.spoiler_text,
+            sensitive: status.sensitive,
+            visibility: status.visibility.clone(),
+            language: status.language.clone(),
+            media_ids: status
+                .media_attachments
+                .iter()
+                .map(|media| media.id.clone())
+                .collect(),
+            poll: status
+                .poll
+                .as_ref()
+                .map(|poll| DraftPoll::new(poll, status.created_at)),
+            deleted: true,
+        }
+    }
+}
+
+impl DraftPoll {
+    /// A poll with the options of `poll`, open for as long as it was when posted at `created_at`.
+    pub fn new(poll: &Poll, created_at: DateTime<Utc>) -> Self {
+        // Mastodon requires polls to be open for at least 5 minutes
+        let expires_in = poll.expires_at.map(|expires_at| {
+            let duration = expires_at.signed_duration_since(created_at).num_seconds();
+            u64::try_from(duration).unwrap_or_default().max(300)
+        });
+        Self {
+            options: poll
+                .options
+                .iter()
+                .map(|option| option.title.clone())
+                .collect(),
+            expires_in,
+            multiple: poll.multiple,
+        }
+    }
+}
+
+impl From<&DraftPoll> for PollOptions {
+    fn from(poll: &DraftPoll) -> Self {
+        Self {
+            options: poll.options.clone(),
+            expires_in: poll.expires_in,
+            multiple: Some(poll.multiple),
+            hide_totals: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    SpoilerText,
+    Text,
+}
+
+/// A form for writing a new status or editing an existing one.
+pub struct Composer {
+    event_sender: Sender<Event>,
+    authentication_data: Arc<RwLock<Option<authentication::State>>>,
+    draft: Draft,
+    spoiler_text: Input,
+    text: TextArea<'static>,
+    focus: Focus,
+    error: Option<String>,
+    /// The status is being sent, so it can't be sent again until the result arrives
+    submitting: bool,
+    /// Asking whether to discard a redraft, whose status no longer exists
+    confirm_discard: bool,
+}
+
+impl Composer {
+    pub fn new(
+        event_sender: Sender<Event>,
+        authentication_data: Arc<RwLock<Option<authentication::State>>>,
+    ) -> Self {
+        Self {
+            event_sender,
+            authentication_data,
+            draft: Draft::default(),
+            spoiler_text: Input::default(),
+            text: TextArea::default(),
+            focus: Focus::Text,
+            error: None,
+            submitting: false,
+            confirm_discard: false,
+        }
+    }
+
+    /// Opens the composer with the contents of `draft`.
+    pub fn start(&mut self, draft: Draft) {
+        info!(edit_id = ?draft.edit_id, "Starting composer component");
+        self.spoiler_text = Input::new(draft.spoiler_text.clone());
+        self.text = TextArea::new(draft.text.lines().map(String::from).collect());
+        self.text.move_cursor(tui_textarea::CursorMove::Bottom);
+        self.text.move_cursor(tui_textarea::CursorMove::End);
+        self.text.set_cursor_line_style(Style::default());
+        self.focus = Focus::Text;
+        self.draft = draft;
+        self.update_text_block();
+        self.error = None;
+        self.submitting = false;
+        self.confirm_discard = false;
+    }
+
+    pub fn title(&self) -> &'static str {
+        if self.draft.edit_id.is_some() {
+            "Edit status"
+        } else {
+            "New status"
+        }
+    }
+
+    pub const fn is_confirming(&self) -> bool {
+        self.confirm_discard
+    }
+
+    /// Whether leaving the composer loses a status: a redraft with text in it, as the status it
+    /// redrafts has already been deleted. Asks the user to confirm if so.
+    pub fn confirm_leave(&mut self) -> bool {
+        let has_text = self.text.lines().iter().any(|line| !line.trim().is_empty());
+        self.confirm_discard = self.draft.deleted && has_text;
+        self.confirm_discard
+    }
+
+    /// Runs an action from the keymap.
+    pub fn handle_action(&mut self, action: Action) -> Outcome {
+        match action {
+            Action::Send => self.submit(),
+            Action::CycleVisibility => self.cycle_visibility(),
+            Action::SwitchField => {
+                self.focus = match self.focus {
+                    Focus::SpoilerText => Focus::Text,
+                    Focus::Text => Focus::SpoilerText,
+                };
+                self.update_text_block();
+            }
+            _ => return Outcome::Ignored,
+        }
+        Outcome::Handled
+    }
+
+    /// Handles key presses, and the result of sending the status: [`Event::StatusPosted`] or
+    /// [`Event::StatusEdited`] once it is sent, or [`Event::ActionFailed`] with the error to show.
+    pub fn handle_event(&mut self, event: &Event) -> Outcome {
+        let event = match event {
+            Event::StatusPosted(_) | Event::StatusEdited(_) if self.submitting => {
+                self.submitting = false;
+                return Outcome::Handled;
+            }
+            Event::ActionFailed(message) if self.submitting => {
+                self.submitting = false;
+                self.error = Some(message.clone());
+                return Outcome::Handled;
+            }
+            Event::Crossterm(event) => event,
+            _ => return Outcome::Ignored,
+        };
+        if self.confirm_discard {
+            if let CrosstermEvent::Key(key) = event {
+                self.confirm_discard = false;
+                if key.code == KeyCode::Char('y') {
+                    self.event_sender.try_send(Event::DraftDiscarded).ok();
+                }
+            }
+            return Outcome::Handled;
+        }
+        match (self.focus, event) {
+            (Focus::Text, CrosstermEvent::Key(key)) => {
+                self.text.input(*key);
+                self.update_text_block();
+            }
+            (Focus::SpoilerText, event) => {
+                self.spoiler_text.handle_event(event);
+            }
+            _ => {}
+        }
+        // swallow everything else so that typing doesn't trigger global shortcuts
+        Outcome::Handled
+    }
+
+    /// Visibility can't be changed when editing a status.
+    fn cycle_visibility(&mut self) {
+        if self.draft.edit_id.is_some() {
+            return;
+        }
+        self.draft.visibility = match self.draft.visibility {
+            StatusVisibility::Public => StatusVisibility::Unlisted,
+            StatusVisibility::Unlisted => StatusVisibility::Private,
+            StatusVisibility::Private => StatusVisibility::Direct,
+            StatusVisibility::Direct => StatusVisibility::Public,
+        };
+    }
+
+    /// Sends the status in the background. Once it is posted the app returns to the timeline,
+    /// otherwise the error is shown in the composer.
+    fn submit(&mut self) {
+        if self.submitting {
+            return;
+        }
+        self.draft.text = self.text.lines().join("\n");
+        self.draft.spoiler_text = self.spoiler_text.value().to_string();
+        let auth = self
+            .authentication_data
+            .read()
+            .expect("lock poisoned")
+            .clone();
+        if auth
+            .as_ref()
+            .is_some_and(|auth| !auth.has_scope("write:statuses"))
+        {
+            self.event_sender
+                .try_send(Event::ScopeRequired("write:statuses".to_string()))
+                .ok();
+            return;
+        }
+        let draft = self.draft.clone();
+        let event_sender = self.event_sender.clone();
+        self.error = None;
+        self.submitting = true;
+        let account = auth.as_ref().map(|auth| auth.name.clone());
+        tokio::spawn(async move {
+            match send(auth, draft).await {
+                Ok(event) => {
+                    event_sender.send(event).await.ok();
+                }
+                Err(err) => {
+                    error!("Unable to send status: {err:#}");
+                    let message = format!("Sending status failed: {err}");
+                    event_sender.send(Event::ActionFailed(message)).await.ok();
+                    // the draft is kept, so it can be sent once the user has signed in again
+                    if let Some(account) = account.filter(|_| authentication::is_unauthorized(&err))
+                    {
+                        event_sender.send(Event::SessionExpired(account)).await.ok();
+                    }
+                }
+            }
+        });
+    }
+
+    /// Updates the border of the text area to show focus and the character count.
+    fn update_text_block(&mut self) {
+        let characters: usize = self
+            .text
+            .lines()
+            .iter()
+            .map(|line| line.chars().count())
+            .sum();
+        self.text.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(self.border_style(Focus::Text))
+                .title(format!("Status ({characters} characters)")),
+        );
+    }
+
+    fn border_style(&self, focus: Focus) -> Style {
+        if self.focus == focus {
+            Style::default().fg(theme::current().accent)
+        } else {
+            Style::default()
+        }
+    }
+}
+
+/// Posts or edits the status, returning the event that updates the timeline.
+async fn send(auth: Option<authentication::State>, draft: Draft) -> Result<Event> {
+    let Some(auth) = auth else {
+        bail!("not logged in");
+    };
+    if draft.text.trim().is_empty() && draft.media_ids.is_empty() {
+        bail!("status is empty");
+    }
+    let client = auth.client()?;
+    if let Some(id) = &draft.edit_id {
+        let options = edit_options(&draft);
+        let status = client.edit_status(id.clone(), &options).await?.json;
+        return Ok(Event::StatusEdited(Box::new(status)));
+    }
+    let options = PostStatusInputOptions {
+        media_ids: Some(draft.media_ids.clone()).filter(|ids| !ids.is_empty()),
+        poll: draft.poll.as_ref().map(PollOptions::from),
+        in_reply_to_id: draft.in_reply_to_id,
+        sensitive: Some(draft.sensitive),
+        spoiler_text: Some(draft.spoiler_text).filter(|text| !text.is_empty()),
+        visibility: Some(draft.visibility),
+        language: draft.language,
+        ..Default::default()
+    };
+    match client.post_status(draft.text, Some(&options)).await?.json {
+        PostStatusOutput::Status(status) => Ok(Event::StatusPosted(Box::new(status))),
+        PostStatusOutput::ScheduledStatus(_) => bail!("unexpected scheduled status"),
+    }
+}
+
+/// The changes to send when editing a status. The poll is left out, as sending it again would
+/// reset its votes and reopen it if it has expired.
+fn edit_options(draft: &Draft) -> EditStatusInputOptions {
+    EditStatusInputOptions {
+        status: Some(draft.text.clone()),
+        spoiler_text: Some(draft.spoiler_text.clone()).filter(|text| !text.is_empty()),
+        sensitive: Some(draft.sensitive),
+        language: draft.language.clone(),
+        media_ids: Some(draft.media_ids.clone()).filter(|ids| !ids.is_empty()),
+        poll: None,
+    }
+}
+
+impl Widget for &Composer {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use Constraint::{Fill, Length};
+        let [spoiler_area, text_area, info_area, error_area] =
+            Layout::vertical([Length(3), Fill(1), Length(1), Length(1)]).areas(area);
+
+        Paragraph::new(self.spoiler_text.value())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.border_style(Focus::SpoilerText))
+                    .title("Content warning"),
+            )
+            .render(spoiler_area, buf);
+
+        self.text.render(text_area, buf);
+
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let mut info = vec![
+            Span::styled("Visibility: ", bold),
+            Span::raw(self.draft.visibility.to_string()),
+        ];
+        if !self.draft.media_ids.is_empty() {
+            info.push(Span::styled(" | Media: ", bold));
+            info.push(Span::raw(format!(
+                "{} attached",
+                self.draft.media_ids.len()
+            )));
+        }
+        if let Some(poll) = &self.draft.poll {
+            info.push(Span::styled(" | Poll: ", bold));
+            info.push(Span::raw(poll.options.join(" / ")));
+        }
+        Line::from(info).render(info_area, buf);
+
+        if self.confirm_discard {
+            Line::styled(
+                "The status was deleted to redraft it. Discard the redraft? (y/n)",
+                Style::default().fg(theme::current().warning),
+            )
+            .render(error_area, buf);
+        } else if self.submitting {
+            Line::raw("Sending...").render(error_area, buf);
+        } else if let Some(error) = &self.error {
+            Line::styled(error.as_str(), Style::default().fg(theme::current().error))
+                .render(error_area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use megalodon::entities::PollOption;
+
+    use super::*;
+
+    fn poll(expires_at: DateTime<Utc>) -> Poll {
+        Poll {
+            id: "1".to_string(),
+            expires_at: Some(expires_at),
+            expired: expires_at < Utc::now(),
+            multiple: false,
+            votes_count: 3,
+            voters_count: Some(3),
+            options: ["yes", "no"]
+                .into_iter()
+                .map(|title| PollOption {
+                    title: title.to_string(),
+                    votes_count: None,
+                })
+                .collect(),
+            voted: None,
+            emojis: vec![],
+        }
+    }
+
+    #[test]
+    fn keeps_the_duration_of_expired_polls() {
+        let created_at = Utc::now() - Duration::days(3);
+        let poll = DraftPoll::new(&poll(created_at + Duration::days(1)), created_at);
+        assert_eq!(poll.options, ["yes", "no"]);
+        assert_eq!(poll.expires_in, Some(24 * 60 * 60));
+    }
+
+    #[test]
+    fn leaves_the_poll_out_of_edits() {
+        let created_at = Utc::now() - Duration::days(3);
+        let draft = Draft {
+            edit_id: Some("1".to_string()),
+            text: "edited".to_string(),
+            poll: Some(DraftPoll::new(
+                &poll(created_at + Duration::hours(1)),
+                created_at,
+            )),
+            ..Draft::default()
+        };
+        let options = edit_options(&draft);
+        assert_eq!(options.status.as_deref(), Some("edited"));
+        assert!(options.poll.is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_once_until_the_result_arrives() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let mut composer = Composer::new(tx, Arc::new(RwLock::new(None)));
+        composer.start(Draft::default());
+        composer.handle_action(Action::Send);
+        composer.handle_action(Action::Send);
+        let event = rx.recv().await.expect("a result");
+        assert!(matches!(event, Event::ActionFailed(_)));
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(composer.handle_event(&event), Outcome::Handled);
+        assert_eq!(
+            composer.error.as_deref(),
+            Some("Sending status failed: not logged in")
+        );
+        // other failures are left to the timeline once the result has arrived
+        assert_eq!(composer.handle_event(&event), Outcome::Ignored);
+    }
+}
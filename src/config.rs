@@ -1,42 +1,256 @@
-use color_eyre::{eyre::WrapErr, Result};
-use mastodon_async::{data::Data, helpers::toml};
-use tracing::info;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
-#[derive(Debug, Clone)]
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use mastodon_async::data::Data;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{crypto, oauth::ClientApp};
+
+/// The accounts that tooters is logged in to, keyed by a name such as `user@mastodon.social`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
+    /// The name of the account to use at startup
+    pub current: Option<String>,
+    #[serde(default)]
+    pub accounts: BTreeMap<String, AccountConfig>,
+}
+
+/// The credentials of an account and the scopes that they grant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountConfig {
+    #[serde(flatten)]
     pub data: Data,
+    /// The space separated scopes granted to the access token. Missing for accounts saved by older
+    /// versions, which asked for every scope.
+    pub scopes: Option<String>,
+    /// The access token encrypted with the user's passphrase (see [`crate::crypto`]). The token
+    /// in `data` is empty when this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_token: Option<String>,
 }
 
-impl From<Data> for Config {
-    fn from(data: Data) -> Self {
-        Self { data }
+impl AccountConfig {
+    /// The account config for `data`, leaving the plain text token out if it is encrypted.
+    pub fn new(mut data: Data, scopes: Option<String>, encrypted_token: Option<String>) -> Self {
+        if encrypted_token.is_some() {
+            data.token = String::new().into();
+        }
+        Self {
+            data,
+            scopes,
+            encrypted_token,
+        }
+    }
+
+    /// The credentials with the access token decrypted with `passphrase` if it is encrypted.
+    pub fn decrypted_data(&self, passphrase: Option<&str>) -> Result<Data> {
+        let Some(encrypted) = &self.encrypted_token else {
+            return Ok(self.data.clone());
+        };
+        let passphrase =
+            passphrase.ok_or_else(|| eyre!("the access token is encrypted with a passphrase"))?;
+        let mut data = self.data.clone();
+        data.token = crypto::decrypt(passphrase, encrypted)?.into();
+        Ok(data)
     }
 }
 
 impl Config {
     /// Loads the config file from the XDG config directory
     /// e.g. ~/.config/tooters/config.toml
+    /// Returns an empty config if the file does not exist yet.
+    /// Config files from older versions, which contained a single account, are migrated to an
+    /// account named after the server.
     pub fn load() -> Result<Self> {
-        let xdg = xdg::BaseDirectories::with_prefix("tooters")?;
-        let config_file = xdg.get_config_file("config.toml");
-        let data = toml::from_file(&config_file).with_context(|| {
+        let config_file = config_file("config.toml")?;
+        if !config_file.exists() {
+            info!("No config file at {}", config_file.display());
+            return Ok(Self::default());
+        }
+        warn_if_readable_by_others(&config_file);
+        let contents = std::fs::read_to_string(&config_file).with_context(|| {
             format!("unable to read config file from {}", &config_file.display())
         })?;
+        let config = match toml::from_str::<Self>(&contents) {
+            Ok(config) if !config.accounts.is_empty() => config,
+            result => match toml::from_str::<Data>(&contents) {
+                Ok(data) => {
+                    info!("Migrating single account config file");
+                    let mut config = Self::default();
+                    let account = AccountConfig {
+                        data,
+                        scopes: None,
+                        encrypted_token: None,
+                    };
+                    config.add_account(server_name(&account.data.base).to_string(), account);
+                    config
+                }
+                Err(_) => result
+                    .with_context(|| format!("invalid config file {}", &config_file.display()))?,
+            },
+        };
         info!("Loaded config file from {}", &config_file.display());
-        Ok(Self { data })
+        Ok(config)
     }
 
     /// Saves the config file to the XDG config directory
     /// e.g. ~/.config/tooters/config.toml
     /// If the file already exists, it will be overwritten
     /// If the directory does not exist, it will be created
+    /// The file is only readable by the user, as it contains access tokens.
     pub fn save(&self) -> Result<()> {
-        let xdg = xdg::BaseDirectories::with_prefix("tooters")?;
-        let config_file = xdg.place_config_file("config.toml")?;
-        toml::to_file(&self.data, &config_file).with_context(|| {
+        let config_file = place_config_file("config.toml")?;
+        let contents = toml::to_string(self).wrap_err("unable to serialize config")?;
+        write_private(&config_file, &contents).with_context(|| {
             format!("unable to write config file to {}", &config_file.display())
         })?;
         info!("Saved config file to {}", &config_file.display());
         Ok(())
     }
+
+    /// The current account, falling back to the first account if none is selected.
+    pub fn current_account(&self) -> Option<(&String, &AccountConfig)> {
+        self.current
+            .as_ref()
+            .and_then(|name| self.accounts.get_key_value(name))
+            .or_else(|| self.accounts.iter().next())
+    }
+
+    /// Adds (or replaces) an account and makes it the current account.
+    pub fn add_account(&mut self, name: String, account: AccountConfig) {
+        self.accounts.insert(name.clone(), account);
+        self.current = Some(name);
+    }
 }
+
+/// The name used for an account, e.g. `user@mastodon.social`.
+pub fn account_name(username: &str, base: &str) -> String {
+    format!("{username}@{}", server_name(base))
+}
+
+fn server_name(base: &str) -> &str {
+    base.trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+}
+
+/// The client apps that tooters has registered, keyed by server url.
+///
+/// These are kept apart from the accounts so that signing in again, or adding another account on
+/// the same server, reuses the registration instead of registering a new app each time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Clients {
+    #[serde(default)]
+    pub servers: BTreeMap<String, ClientApp>,
+}
+
+impl Clients {
+    /// Loads the registered clients from the XDG config directory
+    /// e.g. ~/.config/tooters/clients.toml
+    /// Returns no clients if the file does not exist yet.
+    pub fn load() -> Result<Self> {
+        let clients_file = config_file("clients.toml")?;
+        if !clients_file.exists() {
+            return Ok(Self::default());
+        }
+        warn_if_readable_by_others(&clients_file);
+        let contents = std::fs::read_to_string(&clients_file).with_context(|| {
+            format!(
+                "unable to read clients file from {}",
+                &clients_file.display()
+            )
+        })?;
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid clients file {}", &clients_file.display()))
+    }
+
+    /// Saves the registered clients to the XDG config directory
+    /// e.g. ~/.config/tooters/clients.toml
+    /// The file is only readable by the user, as it contains client secrets.
+    pub fn save(&self) -> Result<()> {
+        let clients_file = place_config_file("clients.toml")?;
+        let contents = toml::to_string(self).wrap_err("unable to serialize clients")?;
+        write_private(&clients_file, &contents).with_context(|| {
+            format!(
+                "unable to write clients file to {}",
+                &clients_file.display()
+            )
+        })?;
+        info!("Saved clients file to {}", &clients_file.display());
+        Ok(())
+    }
+}
+
+/// The directory given with `--config-dir`, which replaces the XDG config directory
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keeps the config, clients and settings files in `dir` instead of the XDG config directory.
+/// Only the first call has an effect.
+pub fn set_config_dir(dir: PathBuf) {
+    CONFIG_DIR.set(dir).ok();
+}
+
+/// The path of the named file in the config directory.
+pub fn config_file(name: &str) -> Result<PathBuf> {
+    if let Some(dir) = CONFIG_DIR.get() {
+        return Ok(dir.join(name));
+    }
+    let xdg = xdg::BaseDirectories::with_prefix("tooters")?;
+    Ok(xdg.get_config_file(name))
+}
+
+/// The path of the named file in the config directory, creating the directory if needed.
+fn place_config_file(name: &str) -> Result<PathBuf> {
+    if let Some(dir) = CONFIG_DIR.get() {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("unable to create config directory {}", dir.display()))?;
+        return Ok(dir.join(name));
+    }
+    let xdg = xdg::BaseDirectories::with_prefix("tooters")?;
+    Ok(xdg.place_config_file(name)?)
+}
+
+/// Writes `contents` to `path` so that only the user can read it. The contents go to a temporary
+/// file in the same directory first, which then replaces `path`, so that a crash while writing
+/// never leaves a truncated file behind.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    // temporary files are created with 0600 permissions on unix
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents.as_bytes())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Warns if other users can read the file at `path`, e.g. because it was written by an older
+/// version of tooters. Saving the file again fixes its permissions.
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+    let mode = metadata.permissions().mode();
+    if mode & 0o077 != 0 {
+        warn!(
+            "{} is readable by other users (mode {:o}), run `chmod 600 {}` to protect the \
+             credentials in it",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &Path) {}
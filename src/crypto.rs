@@ -0,0 +1,95 @@
+//! Encrypts access tokens with a passphrase, so that they are not stored in plain text.
+//!
+//! The key is derived from the passphrase with Argon2id and a random salt, and the token is
+//! encrypted with ChaCha20-Poly1305. The salt, nonce and ciphertext are stored together, base64
+//! encoded.
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use rand::RngCore;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts `plaintext` with a key derived from `passphrase`.
+pub fn encrypt(passphrase: &str, plaintext: &str) -> Result<String> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|_| eyre!("unable to encrypt"))?;
+    Ok(STANDARD.encode([&salt[..], &nonce, &ciphertext].concat()))
+}
+
+/// Decrypts text from [`encrypt`]. Fails if the passphrase is wrong.
+pub fn decrypt(passphrase: &str, encrypted: &str) -> Result<String> {
+    let bytes = STANDARD
+        .decode(encrypted)
+        .wrap_err("invalid encrypted text")?;
+    if bytes.len() < SALT_LEN + NONCE_LEN {
+        bail!("invalid encrypted text");
+    }
+    let (salt, rest) = bytes.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let plaintext = cipher(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| eyre!("wrong passphrase"))?;
+    String::from_utf8(plaintext).wrap_err("invalid encrypted text")
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| eyre!("unable to derive key from passphrase: {err}"))?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let encrypted = encrypt("correct horse", "token-123").unwrap();
+        assert!(!encrypted.contains("token-123"));
+        assert_eq!(decrypt("correct horse", &encrypted).unwrap(), "token-123");
+        // a random salt and nonce make every encryption different
+        assert_ne!(encrypt("correct horse", "token-123").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase() {
+        let encrypted = encrypt("correct horse", "token-123").unwrap();
+        let err = decrypt("battery staple", &encrypted).unwrap_err();
+        assert_eq!(err.to_string(), "wrong passphrase");
+    }
+
+    #[test]
+    fn rejects_damaged_input() {
+        let encrypted = encrypt("correct horse", "token-123").unwrap();
+        let bytes = STANDARD.decode(&encrypted).unwrap();
+        // too short to hold a salt and nonce
+        assert!(decrypt("correct horse", &STANDARD.encode(&bytes[..20])).is_err());
+        // a salt and nonce, but no ciphertext
+        let header = &bytes[..SALT_LEN + NONCE_LEN];
+        assert!(decrypt("correct horse", &STANDARD.encode(header)).is_err());
+        // truncated ciphertext
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(decrypt("correct horse", &STANDARD.encode(truncated)).is_err());
+        // a flipped bit in the ciphertext
+        let mut garbled = bytes.clone();
+        *garbled.last_mut().unwrap() ^= 1;
+        assert!(decrypt("correct horse", &STANDARD.encode(&garbled)).is_err());
+        // not base64 at all
+        assert!(decrypt("correct horse", "not base64!").is_err());
+        assert!(decrypt("correct horse", "").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use megalodon::entities::Emoji;
+use ratatui::{
+    style::{Modifier, Style},
+    text::Span,
+};
+
+use crate::theme;
+
+/// Marks where a `:shortcode:` starts and ends in text from [`mark`], so that shortcodes can be
+/// found again after the text is wrapped, even when one is split across lines. The invisible
+/// separator takes up no columns, so it doesn't change where lines wrap.
+const MARKER: char = '\u{2063}';
+
+/// Splits `text` into spans, styling the `:shortcode:` of each known custom emoji so that it stands
+/// out from the surrounding text.
+///
+/// Only shortcodes present in `emojis` are treated as custom emojis, so text like `12:30:45` is
+/// left alone. When `strip` is set (screen reader mode) the shortcodes are removed entirely rather
+/// than being read out character by character.
+pub fn emojify(text: &str, emojis: &[Emoji], style: Style, strip: bool) -> Vec<Span<'static>> {
+    spans(&mark(text, emojis, strip), style, &mut false)
+}
+
+/// Marks the known `:shortcode:`s in `text` for [`spans`], or removes them when `strip` is set.
+/// Text that is wrapped must be marked before wrapping, so that shortcodes split across lines are
+/// still found.
+pub fn mark(text: &str, emojis: &[Emoji], strip: bool) -> String {
+    let text = text.replace(MARKER, "");
+    let mut marked = String::new();
+    let mut rest = text.as_str();
+    while let Some((shortcode, start, end)) = next_shortcode(rest, emojis) {
+        marked.push_str(&rest[..start]);
+        let mut after = &rest[end..];
+        if strip {
+            // collapse the whitespace around the shortcode, leaving the rest of the text alone
+            if marked.ends_with(' ') && (after.is_empty() || after.starts_with(' ')) {
+                marked.pop();
+            } else if (marked.is_empty() || marked.ends_with('\n')) && after.starts_with(' ') {
+                after = &after[1..];
+            }
+        } else {
+            marked.push(MARKER);
+            marked.push_str(&format!(":{shortcode}:"));
+            marked.push(MARKER);
+        }
+        rest = after;
+    }
+    marked.push_str(rest);
+    marked
+}
+
+/// Splits a line of text from [`mark`] into spans, styling the shortcodes. `in_emoji` carries
+/// whether the previous line ended in the middle of a shortcode.
+pub fn spans(line: &str, style: Style, in_emoji: &mut bool) -> Vec<Span<'static>> {
+    let emoji_style = style
+        .fg(theme::current().emoji)
+        .add_modifier(Modifier::ITALIC);
+    let mut spans = vec![];
+    for (index, part) in line.split(MARKER).enumerate() {
+        if index > 0 {
+            *in_emoji = !*in_emoji;
+        }
+        if !part.is_empty() {
+            let style = if *in_emoji { emoji_style } else { style };
+            spans.push(Span::styled(part.to_string(), style));
+        }
+    }
+    spans
+}
+
+/// Removes the custom emoji shortcodes from `text`, returning `fallback` if nothing is left.
+pub fn strip(text: &str, emojis: &[Emoji], fallback: &str) -> String {
+    let stripped = mark(text, emojis, true);
+    if stripped.trim().is_empty() {
+        fallback.to_string()
+    } else {
+        stripped
+    }
+}
+
+/// Finds the first known `:shortcode:` in `text`, returning it with its byte range.
+fn next_shortcode<'a>(text: &str, emojis: &'a [Emoji]) -> Option<(&'a str, usize, usize)> {
+    emojis
+        .iter()
+        .filter_map(|emoji| {
+            let pattern = format!(":{}:", emoji.shortcode);
+            text.find(&pattern)
+                .map(|start| (emoji.shortcode.as_str(), start, start + pattern.len()))
+        })
+        .min_by_key(|(_, start, _)| *start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emoji(shortcode: &str) -> Emoji {
+        Emoji {
+            shortcode: shortcode.to_string(),
+            static_url: String::new(),
+            url: String::new(),
+            visible_in_picker: true,
+            category: None,
+        }
+    }
+
+    fn texts(spans: &[Span]) -> Vec<String> {
+        spans.iter().map(|span| span.content.to_string()).collect()
+    }
+
+    #[test]
+    fn styles_known_shortcodes() {
+        let emojis = [emoji("blobcat")];
+        let spans = emojify("hi :blobcat: there", &emojis, Style::default(), false);
+        assert_eq!(texts(&spans), ["hi ", ":blobcat:", " there"]);
+        assert_eq!(spans[0].style, Style::default());
+        assert!(spans[1].style.add_modifier.contains(Modifier::ITALIC));
+        assert_eq!(spans[1].style.fg, Some(theme::current().emoji));
+    }
+
+    #[test]
+    fn leaves_unknown_shortcodes_and_times_alone() {
+        let emojis = [emoji("blobcat")];
+        let spans = emojify("at 12:30:45 :unknown:", &emojis, Style::default(), false);
+        assert_eq!(texts(&spans), ["at 12:30:45 :unknown:"]);
+    }
+
+    #[test]
+    fn strips_shortcodes_and_the_space_they_leave() {
+        let emojis = [emoji("blobcat")];
+        let strip = |text| mark(text, &emojis, true);
+        assert_eq!(strip("hi :blobcat: there"), "hi there");
+        assert_eq!(strip(":blobcat: hi"), "hi");
+        assert_eq!(strip("hi :blobcat:"), "hi");
+        assert_eq!(strip("  - item :blobcat:"), "  - item");
+        assert_eq!(strip("a  b :blobcat:"), "a  b");
+    }
+
+    #[test]
+    fn falls_back_when_nothing_is_left() {
+        let emojis = [emoji("blobcat")];
+        assert_eq!(strip(":blobcat:", &emojis, "alice"), "alice");
+        assert_eq!(strip(":blobcat: :blobcat:", &emojis, "alice"), "alice");
+        assert_eq!(strip("Alice :blobcat:", &emojis, "alice"), "Alice");
+    }
+
+    #[test]
+    fn styles_shortcodes_split_across_lines() {
+        let emojis = [emoji("a_very_long_shortcode")];
+        let marked = mark("x :a_very_long_shortcode: y", &emojis, false);
+        let lines: Vec<String> = crate::wrap::wrap(&marked, 12)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert!(lines.len() > 2);
+        let mut in_emoji = false;
+        let styled: Vec<Vec<Span>> = lines
+            .iter()
+            .map(|line| spans(line, Style::default(), &mut in_emoji))
+            .collect();
+        let emoji_text: String = styled
+            .iter()
+            .flatten()
+            .filter(|span| span.style.add_modifier.contains(Modifier::ITALIC))
+            .map(|span| span.content.to_string())
+            .collect();
+        assert_eq!(emoji_text, ":a_very_long_shortcode:");
+        assert!(!in_emoji);
+    }
+}
@@ -3,6 +3,7 @@ use std::time::Duration;
 use color_eyre::Result;
 use crossterm::event::{Event as CrosstermEvent, EventStream};
 use futures::StreamExt;
+use megalodon::entities::Status;
 use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
 use signal_hook_tokio::Signals;
 use tokio::{
@@ -11,15 +12,49 @@ use tokio::{
 };
 use tracing::{error, info, trace};
 
+use crate::{api::StatusEdit, composer::Draft, home::Timeline, settings::Settings};
+
 /// The tick rate for the tick event (60fps)
 const TICK_RATE: Duration = Duration::from_millis(1000 / 60);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Event {
     Tick,
     Quit,
     Crossterm(CrosstermEvent),
     AuthenticationSuccess,
+    /// Show the accounts in the config file
+    ShowAccounts,
+    /// Log in to the named account from the config file
+    SwitchAccount(String),
+    /// Log in to a new account
+    AddAccount,
+    /// Log out of the named account, revoking its access token
+    Logout(String),
+    /// The server rejected the access token of the named account, so the user must sign in again
+    SessionExpired(String),
+    /// An action needs a scope that the access token does not grant, so the user must sign in again
+    ScopeRequired(String),
+    /// Show the edit history of the status with the given id
+    ShowHistory(String),
+    /// The revisions of the status with the given id were loaded
+    HistoryLoaded(String, Vec<StatusEdit>),
+    /// Open the composer with a draft status
+    Compose(Box<Draft>),
+    StatusPosted(Box<Status>),
+    StatusEdited(Box<Status>),
+    /// The statuses of a timeline were loaded, with the title to show above them
+    TimelineLoaded(Timeline, String, Vec<Status>),
+    /// The status with the given id was deleted
+    StatusDeleted(String),
+    /// The user discarded the draft in the composer
+    DraftDiscarded,
+    /// A background action failed, with a message to show to the user
+    ActionFailed(String),
+    /// The settings file changed, with the new settings to apply
+    SettingsChanged(Box<Settings>),
+    /// The settings file changed but is not valid, so the last good settings are kept
+    SettingsInvalid(String),
 }
 
 #[derive(Debug)]
@@ -0,0 +1,155 @@
+use crossterm::event::Event as CrosstermEvent;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+use tui_input::{backend::crossterm::EventHandler, Input};
+
+use crate::{
+    keymap::{Action, Category, Context, Keymap},
+    theme,
+};
+
+/// Lists the actions of a view and their keys over the view, filtered by a search.
+///
+/// The list is built from the keymap when the overlay opens, so it always shows the keys that the
+/// view actually responds to.
+#[derive(Debug)]
+pub struct Help {
+    view: &'static str,
+    entries: Vec<Entry>,
+    search: Input,
+    scroll: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    action: Action,
+    /// The keys of every binding of the action, e.g. `j, ↓`
+    keys: String,
+}
+
+impl Help {
+    const WIDTH: u16 = 72;
+
+    pub fn new(keymap: &Keymap, context: Context) -> Self {
+        let entries = keymap
+            .actions(context)
+            .into_iter()
+            .map(|(action, bindings)| {
+                let keys: Vec<String> =
+                    bindings.iter().map(|binding| binding.keys_text()).collect();
+                Entry {
+                    action,
+                    keys: keys.join(", "),
+                }
+            })
+            .collect();
+        Self {
+            view: context.name(),
+            entries,
+            search: Input::default(),
+            scroll: 0,
+        }
+    }
+
+    /// Types into the search.
+    pub fn handle_event(&mut self, event: &CrosstermEvent) {
+        if self.search.handle_event(event).is_some() {
+            self.scroll = 0;
+        }
+    }
+
+    pub fn scroll_down(&mut self, count: usize) {
+        let last = self.lines().len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add(count).min(last);
+    }
+
+    pub fn scroll_up(&mut self, count: usize) {
+        self.scroll = self.scroll.saturating_sub(count);
+    }
+
+    /// The entries that match the search, in any of their action name, description or keys.
+    fn matches(&self) -> impl Iterator<Item = &Entry> {
+        let search = self.search.value().to_lowercase();
+        self.entries.iter().filter(move |entry| {
+            [entry.action.name(), entry.action.description(), &entry.keys]
+                .iter()
+                .any(|text| text.to_lowercase().contains(&search))
+        })
+    }
+
+    /// The matching entries under a heading for each category.
+    fn lines(&self) -> Vec<Line<'_>> {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let gray = Style::default().fg(theme::current().muted);
+        let key_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.keys.chars().count())
+            .max()
+            .unwrap_or_default();
+        let mut lines = vec![];
+        let mut category: Option<Category> = None;
+        for entry in self.matches() {
+            if category != Some(entry.action.category()) {
+                if category.is_some() {
+                    lines.push(Line::default());
+                }
+                category = Some(entry.action.category());
+                lines.push(Line::styled(entry.action.category().title(), bold));
+            }
+            let keys = if entry.keys.is_empty() {
+                Span::styled(format!("  {:key_width$}  ", "unbound"), gray)
+            } else {
+                Span::styled(format!("  {:key_width$}  ", entry.keys), bold)
+            };
+            lines.push(Line::from(vec![
+                keys,
+                Span::raw(entry.action.description()),
+            ]));
+        }
+        if lines.is_empty() {
+            lines.push(Line::styled("No matching keys", gray));
+        }
+        lines
+    }
+}
+
+impl Widget for &Help {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let lines = self.lines();
+        let height = u16::try_from(lines.len() + 4).unwrap_or(u16::MAX);
+        let [area] = Layout::horizontal([Constraint::Length(Help::WIDTH)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        let block = Block::bordered().title(format!(" Keys: {} ", self.view));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let [search, _, list] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+        let gray = Style::default().fg(theme::current().muted);
+        let search_line = if self.search.value().is_empty() {
+            Line::styled("Type to search", gray)
+        } else {
+            Line::from(vec![
+                Span::styled("Search: ", gray),
+                Span::raw(self.search.value()),
+            ])
+        };
+        search_line.render(search, buf);
+        let scroll = u16::try_from(self.scroll).unwrap_or(u16::MAX);
+        Paragraph::new(lines).scroll((scroll, 0)).render(list, buf);
+    }
+}
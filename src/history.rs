@@ -0,0 +1,223 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{format::Item, Local};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Paragraph, Widget, Wrap},
+};
+use similar::{ChangeTag, TextDiff};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{self, StatusEdit},
+    authentication,
+    event::{Event, Outcome},
+    home::UNWRAPPED_WIDTH,
+    keymap::Action,
+    settings, theme,
+};
+
+/// Shows every revision of an edited status, with a word level diff against the previous revision.
+pub struct History {
+    event_sender: Sender<Event>,
+    authentication_data: Arc<RwLock<Option<authentication::State>>>,
+    /// The status whose history is shown
+    status_id: String,
+    revisions: Option<Vec<StatusEdit>>,
+    status: String,
+    scroll: u16,
+    time_format: Vec<Item<'static>>,
+}
+
+impl History {
+    pub fn new(
+        event_sender: Sender<Event>,
+        authentication_data: Arc<RwLock<Option<authentication::State>>>,
+        settings: &settings::Ui,
+    ) -> Self {
+        Self {
+            event_sender,
+            authentication_data,
+            status_id: String::new(),
+            revisions: None,
+            status: String::new(),
+            scroll: 0,
+            time_format: settings.time_format_items().unwrap_or_default(),
+        }
+    }
+
+    /// Applies settings that changed while the app is running.
+    pub fn apply_settings(&mut self, settings: &settings::Ui) {
+        self.time_format = settings.time_format_items().unwrap_or_default();
+    }
+
+    /// Starts loading the edit history of the status with the given id in the background. The
+    /// revisions arrive as [`Event::HistoryLoaded`], or a failure as [`Event::ActionFailed`].
+    pub fn start(&mut self, status_id: &str) {
+        info!(status_id, "Starting history component");
+        self.status_id = status_id.to_string();
+        self.revisions = None;
+        self.scroll = 0;
+        self.status = "Loading edit history...".to_string();
+        let auth = self
+            .authentication_data
+            .read()
+            .expect("lock poisoned")
+            .clone();
+        let Some(auth) = auth else {
+            warn!("Not logged in, unable to load the edit history");
+            return;
+        };
+        let event_sender = self.event_sender.clone();
+        let status_id = status_id.to_string();
+        tokio::spawn(async move {
+            let event = match api::status_history(&auth, &status_id).await {
+                Ok(revisions) => Event::HistoryLoaded(status_id, revisions),
+                Err(err) => {
+                    error!("Unable to load status history: {err:#}");
+                    if authentication::is_unauthorized(&err) {
+                        Event::SessionExpired(auth.name)
+                    } else {
+                        Event::ActionFailed(format!("Loading edit history failed: {err}"))
+                    }
+                }
+            };
+            event_sender.send(event).await.ok();
+        });
+    }
+
+    /// Shows the revisions loaded by [`History::start`], unless another status was opened since.
+    pub fn load(&mut self, status_id: &str, revisions: Vec<StatusEdit>) {
+        if status_id != self.status_id {
+            return;
+        }
+        self.status = format!("{} revisions", revisions.len());
+        self.revisions = Some(revisions);
+    }
+
+    /// Whether the history is still being loaded.
+    pub const fn is_loading(&self) -> bool {
+        self.revisions.is_none()
+    }
+
+    /// Runs an action from the keymap, scrolling `count` lines.
+    pub fn handle_action(&mut self, action: Action, count: usize) -> Outcome {
+        let count = u16::try_from(count).unwrap_or(u16::MAX);
+        match action {
+            Action::Down => self.scroll = self.scroll.saturating_add(count),
+            Action::Up => self.scroll = self.scroll.saturating_sub(count),
+            Action::Top => self.scroll = 0,
+            _ => return Outcome::Ignored,
+        }
+        Outcome::Handled
+    }
+
+    pub const fn title() -> &'static str {
+        "Edit history"
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+impl Widget for &History {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let Some(revisions) = &self.revisions else {
+            Paragraph::new("Loading edit history...").render(area, buf);
+            return;
+        };
+        let mut text = Text::default();
+        let mut previous = None;
+        for (index, revision) in revisions.iter().enumerate() {
+            text.extend(revision_text(index, revision, previous, &self.time_format));
+            previous = Some(revision);
+        }
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .render(area, buf);
+    }
+}
+
+/// The header, content warning and content of a revision, diffed against the previous revision.
+fn revision_text(
+    index: usize,
+    revision: &StatusEdit,
+    previous: Option<&StatusEdit>,
+    time_format: &[Item<'static>],
+) -> Text<'static> {
+    let date = revision
+        .created_at
+        .with_timezone(&Local)
+        .format_with_items(time_format.iter());
+    let label = if index == 0 {
+        "Original".to_string()
+    } else {
+        format!("Edit {index}")
+    };
+    let mut text = Text::from(Line::styled(
+        format!("{label} · {date}"),
+        Style::default()
+            .fg(theme::current().accent)
+            .add_modifier(Modifier::BOLD),
+    ));
+
+    let previous_spoiler = previous.map_or(revision.spoiler_text.as_str(), |p| &p.spoiler_text);
+    if !revision.spoiler_text.is_empty() || !previous_spoiler.is_empty() {
+        let mut lines = diff_lines(previous_spoiler, &revision.spoiler_text);
+        lines[0].spans.insert(
+            0,
+            Span::styled("CW: ", Style::default().add_modifier(Modifier::BOLD)),
+        );
+        text.extend(lines);
+    }
+
+    let content = plain_text(&revision.content);
+    let previous_content = previous.map_or_else(|| content.clone(), |p| plain_text(&p.content));
+    text.extend(diff_lines(&previous_content, &content));
+    text.push_line("");
+    text
+}
+
+fn plain_text(html: &str) -> String {
+    html2text::from_read(html.as_bytes(), UNWRAPPED_WIDTH).unwrap_or_default()
+}
+
+/// Diffs two texts word by word, marking removed words in red with a strikethrough and added words
+/// in green with an underline.
+fn diff_lines(old: &str, new: &str) -> Vec<Line<'static>> {
+    let diff = TextDiff::from_words(old, new);
+    let theme = theme::current();
+    let mut lines = vec![Line::default()];
+    for change in diff.iter_all_changes() {
+        let style = match change.tag() {
+            ChangeTag::Equal => Style::default(),
+            ChangeTag::Delete => Style::default()
+                .fg(theme.removed)
+                .add_modifier(Modifier::CROSSED_OUT),
+            ChangeTag::Insert => Style::default()
+                .fg(theme.added)
+                .add_modifier(Modifier::UNDERLINED),
+        };
+        for (i, part) in change.value().split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Line::default());
+            }
+            if !part.is_empty() {
+                if let Some(line) = lines.last_mut() {
+                    line.push_span(Span::styled(part.to_string(), style));
+                }
+            }
+        }
+    }
+    // html2text ends the text with a newline, which would otherwise leave an empty last line
+    if lines.len() > 1 && lines.last().is_some_and(|line| line.spans.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
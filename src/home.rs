@@ -1,121 +1,411 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
 
+use chrono::{format::Item, DateTime, Datelike, Local, Utc};
 use color_eyre::{
-    eyre::{bail, Context},
+    eyre::{bail, eyre, Context},
     Result,
 };
-use crossterm::event::{Event as CrosstermEvent, KeyCode, KeyModifiers};
-use mastodon_async::prelude::Status;
+use crossterm::event::{Event as CrosstermEvent, KeyCode};
+use megalodon::{
+    entities::{status::StatusVisibility, Account, Status},
+    megalodon::{
+        GetAccountStatusesInputOptions, GetHomeTimelineInputOptions, GetListTimelineInputOptions,
+        GetTagTimelineInputOptions, SearchAccountInputOptions,
+    },
+    Megalodon,
+};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
-    style::{Color, Modifier, Style},
+    layout::{Alignment, Rect},
+    style::{Modifier, Style},
     text::{Line, Span, Text},
     widgets::{List, ListItem, ListState, StatefulWidget, Widget},
 };
-use time::format_description;
 use tokio::sync::mpsc::Sender;
-use tracing::info;
+use tracing::{error, info, warn};
 
 use crate::{
     authentication,
+    composer::Draft,
+    emoji,
     event::{Event, Outcome},
+    keymap::Action,
+    settings::Settings,
+    theme, wrap,
 };
 
+/// A width large enough that html2text leaves paragraphs on a single line.
+pub const UNWRAPPED_WIDTH: usize = 10_000;
+
 pub struct Home {
-    _event_sender: Sender<Event>,
+    event_sender: Sender<Event>,
     authentication_data: Arc<RwLock<Option<authentication::State>>>,
     title: String,
+    /// Which statuses are shown
+    source: Timeline,
     timeline: Option<Vec<Status>>,
     status: String,
     list_state: Arc<RwLock<ListState>>,
+    /// The current time, updated on every tick so that relative ages stay fresh
+    now: DateTime<Utc>,
+    /// Parsed strftime items for displaying absolute timestamps in the local timezone
+    time_format: Vec<Item<'static>>,
+    /// Strip custom emoji shortcodes for screen readers
+    strip_emojis: bool,
+    /// How many statuses to load
+    timeline_limit: u32,
+    /// A destructive action waiting for the user to confirm it with `y`
+    confirm: Option<Confirm>,
+}
+
+enum Confirm {
+    Delete(Status),
+    Redraft(Status),
+}
+
+/// The statuses that the home view shows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Timeline {
+    /// The statuses of the accounts that the user follows
+    #[default]
+    Home,
+    /// The statuses with a hashtag, given without the `#`
+    Tag(String),
+    /// The statuses in the user's list with this title
+    List(String),
+    /// The statuses posted by the account with this address, e.g. `user@mastodon.social`
+    User(String),
 }
 
 impl Home {
     pub fn new(
         event_sender: Sender<Event>,
         authentication_data: Arc<RwLock<Option<authentication::State>>>,
+        settings: &Settings,
     ) -> Self {
         Self {
-            _event_sender: event_sender,
+            event_sender,
             authentication_data,
             title: String::new(),
+            source: Timeline::Home,
             timeline: None,
             status: String::new(),
             list_state: Arc::new(RwLock::new(ListState::default())),
+            now: Utc::now(),
+            time_format: settings.ui.time_format_items().unwrap_or_default(),
+            strip_emojis: settings.ui.screen_reader,
+            timeline_limit: settings.timelines.limit,
+            confirm: None,
         }
     }
 
+    /// Applies settings that changed while the app is running. The timeline is drawn with the new
+    /// settings, and loaded with them the next time it is refreshed.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.time_format = settings.ui.time_format_items().unwrap_or_default();
+        self.strip_emojis = settings.ui.screen_reader;
+        self.timeline_limit = settings.timelines.limit;
+    }
+
     pub async fn start(&mut self) -> Result<()> {
         info!("Starting home component");
+        self.show(self.source.clone()).await
+    }
+
+    /// Loads and shows another timeline. The current timeline stays if it fails to load.
+    pub async fn show(&mut self, source: Timeline) -> Result<()> {
         let auth = Arc::clone(&self.authentication_data);
         let auth = auth.read().expect("lock poisoned").clone(); // easy way to avoid holding the lock over the await below
-        if let Some(auth) = auth {
-            let username = auth.account.username.clone();
-            let server = auth.config.data.base.trim_start_matches("https://");
-            self.title = format!("{username}@{server}");
-            let page = auth
-                .mastodon
-                .get_home_timeline()
-                .await
-                .wrap_err("failed to load timeline")?;
-            self.timeline = Some(page.initial_items);
-        } else {
+        let Some(auth) = auth else {
             self.title = "Not logged in".to_string();
             bail!("not logged in");
-        }
+        };
+        let (title, statuses) = fetch(&auth, &source, self.timeline_limit).await?;
+        self.set_timeline(source, title, statuses);
         Ok(())
     }
 
+    /// Loads another timeline in the background, showing it once it arrives as
+    /// [`Event::TimelineLoaded`]. The current timeline stays if it fails to load.
+    pub fn open(&mut self, source: Timeline) {
+        let limit = self.timeline_limit;
+        self.spawn_action(
+            "Load timeline",
+            "read:statuses",
+            move |auth, event_sender| async move {
+                let (title, statuses) = fetch(&auth, &source, limit).await?;
+                let event = Event::TimelineLoaded(source, title, statuses);
+                event_sender.send(event).await?;
+                Ok(())
+            },
+        );
+    }
+
+    fn set_timeline(&mut self, source: Timeline, title: String, statuses: Vec<Status>) {
+        self.title = title;
+        self.source = source;
+        self.timeline = Some(statuses);
+        self.list_state.write().expect("lock poisoned").select(None);
+    }
+
+    /// Whether a destructive action is waiting for the user to press `y`, which takes every key.
+    pub const fn is_confirming(&self) -> bool {
+        self.confirm.is_some()
+    }
+
+    /// Runs an action from the keymap, `count` times where that makes sense.
+    pub fn handle_action(&mut self, action: Action, count: usize) -> Outcome {
+        match action {
+            Action::Down => self.scroll_down(count),
+            Action::Up => self.scroll_up(count),
+            Action::Top => self.select(0),
+            Action::Bottom => {
+                let len = self.timeline.as_ref().map_or(0, Vec::len);
+                self.select(len.saturating_sub(1));
+            }
+            Action::ShowHistory => self.show_history(),
+            Action::Compose => self.send_event(Event::Compose(Box::default())),
+            Action::ShowAccounts => self.send_event(Event::ShowAccounts),
+            Action::Edit => self.edit(),
+            Action::Delete => {
+                if let Some(status) = self.own_selected_status() {
+                    self.status = "Delete this status? (y/n)".to_string();
+                    self.confirm = Some(Confirm::Delete(status));
+                }
+            }
+            Action::Redraft => {
+                if let Some(status) = self.own_selected_status() {
+                    self.status = "Delete and redraft this status? (y/n)".to_string();
+                    self.confirm = Some(Confirm::Redraft(status));
+                }
+            }
+            _ => return Outcome::Ignored,
+        }
+        Outcome::Handled
+    }
+
     pub fn handle_event(&mut self, event: &Event) -> Outcome {
         match event {
-            Event::Crossterm(event) => {
-                if let CrosstermEvent::Key(key) = *event {
-                    match (key.modifiers, key.code) {
-                        (KeyModifiers::NONE, KeyCode::Char('j')) => {
-                            self.scroll_down();
-                        }
-                        (KeyModifiers::NONE, KeyCode::Char('k')) => {
-                            self.scroll_up();
-                        }
-                        _ => return Outcome::Ignored,
+            Event::Crossterm(CrosstermEvent::Key(key)) => {
+                let Some(confirm) = self.confirm.take() else {
+                    return Outcome::Ignored;
+                };
+                self.confirm_action(confirm, key.code == KeyCode::Char('y'));
+                Outcome::Handled
+            }
+            Event::Tick => {
+                self.now = Utc::now();
+                Outcome::Handled
+            }
+            Event::StatusPosted(status) => {
+                if let Some(timeline) = self
+                    .timeline
+                    .as_mut()
+                    .filter(|_| self.source == Timeline::Home)
+                {
+                    timeline.insert(0, *status.clone());
+                }
+                self.status = "Status posted".to_string();
+                Outcome::Handled
+            }
+            Event::StatusEdited(edited) => {
+                for status in self.timeline.iter_mut().flatten() {
+                    if status.id == edited.id {
+                        *status = *edited.clone();
+                    } else if let Some(reblog) =
+                        status.reblog.as_mut().filter(|r| r.id == edited.id)
+                    {
+                        *reblog = edited.clone();
                     }
                 }
+                self.status = "Status edited".to_string();
+                Outcome::Handled
+            }
+            Event::StatusDeleted(id) => {
+                if let Some(timeline) = &mut self.timeline {
+                    timeline.retain(|status| {
+                        status.id != *id && status.reblog.as_ref().is_none_or(|r| r.id != *id)
+                    });
+                }
+                self.status = "Status deleted".to_string();
+                Outcome::Handled
+            }
+            Event::ActionFailed(message) => {
+                self.status.clone_from(message);
+                Outcome::Handled
+            }
+            Event::TimelineLoaded(source, title, statuses) => {
+                self.set_timeline(source.clone(), title.clone(), statuses.clone());
+                self.status.clear();
                 Outcome::Handled
             }
             _ => Outcome::Ignored,
         }
     }
 
-    fn scroll_down(&mut self) {
-        // self.selected += 1;
-        let list_state = Arc::clone(&self.list_state);
-        let mut list_state = list_state.write().expect("lock poisoned");
-        let index = list_state.selected().map_or(0, |s| s + 1);
-        list_state.select(Some(index));
-        drop(list_state); // release the lock before calling update_status(
-        self.update_status(index);
+    fn scroll_down(&mut self, count: usize) {
+        let selected = self.list_state.read().expect("lock poisoned").selected();
+        let len = self.timeline.as_ref().map_or(0, Vec::len);
+        let index = selected.map_or(count - 1, |s| s.saturating_add(count));
+        self.select(index.min(len.saturating_sub(1)));
     }
 
-    fn scroll_up(&mut self) {
-        // self.selected = self.selected.saturating_sub(1);
+    fn scroll_up(&mut self, count: usize) {
+        let selected = self.list_state.read().expect("lock poisoned").selected();
+        self.select(selected.map_or(0, |s| s.saturating_sub(count)));
+    }
+
+    fn select(&mut self, index: usize) {
         let list_state = Arc::clone(&self.list_state);
         let mut list_state = list_state.write().expect("lock poisoned");
-        let index = list_state.selected().map_or(0, |s| s.saturating_sub(1));
         list_state.select(Some(index));
         drop(list_state); // release the lock before calling update_status(
         self.update_status(index);
     }
 
+    /// The selected status, or the boosted status if the selected status is a reblog.
+    fn selected_status(&self) -> Option<&Status> {
+        let selected = self.list_state.read().expect("lock poisoned").selected()?;
+        let status = self.timeline.as_ref()?.get(selected)?;
+        Some(status.reblog.as_deref().unwrap_or(status))
+    }
+
+    /// The selected status if it was written by the logged in account.
+    fn own_selected_status(&mut self) -> Option<Status> {
+        let auth = self
+            .authentication_data
+            .read()
+            .expect("lock poisoned")
+            .clone()?;
+        let status = self.selected_status()?.clone();
+        if status.account.id != auth.account.id.as_ref() {
+            self.status = "You can only edit or delete your own statuses".to_string();
+            return None;
+        }
+        Some(status)
+    }
+
+    /// Fetches the source of the selected status and opens it in the composer.
+    fn edit(&mut self) {
+        let Some(status) = self.own_selected_status() else {
+            return;
+        };
+        self.spawn_action("Edit", "read:statuses", |auth, event_sender| async move {
+            let source = auth
+                .client()?
+                .get_status_source(status.id.clone())
+                .await?
+                .json;
+            let draft = Draft::edit(&status, source);
+            event_sender.send(Event::Compose(Box::new(draft))).await?;
+            Ok(())
+        });
+    }
+
+    fn confirm_action(&mut self, confirm: Confirm, confirmed: bool) {
+        if !confirmed {
+            self.status = "Cancelled".to_string();
+            return;
+        }
+        match confirm {
+            Confirm::Delete(status) => {
+                self.spawn_action(
+                    "Delete",
+                    "write:statuses",
+                    |auth, event_sender| async move {
+                        auth.client()?.delete_status(status.id.clone()).await?;
+                        event_sender.send(Event::StatusDeleted(status.id)).await?;
+                        Ok(())
+                    },
+                );
+            }
+            Confirm::Redraft(status) => {
+                self.spawn_action(
+                    "Delete and redraft",
+                    "write:statuses",
+                    |auth, event_sender| async move {
+                        // the source is only available while the status exists
+                        let client = auth.client()?;
+                        let source = client.get_status_source(status.id.clone()).await?.json;
+                        client.delete_status(status.id.clone()).await?;
+                        event_sender
+                            .send(Event::StatusDeleted(status.id.clone()))
+                            .await?;
+                        let draft = Draft::redraft(&status, source);
+                        event_sender.send(Event::Compose(Box::new(draft))).await?;
+                        Ok(())
+                    },
+                );
+            }
+        }
+    }
+
+    /// Runs an API action in the background, showing any failure in the status bar. If the access
+    /// token lacks the `scope` that the action needs, asks the user to sign in again instead.
+    fn spawn_action<F, Fut>(&mut self, name: &'static str, scope: &'static str, action: F)
+    where
+        F: FnOnce(authentication::State, Sender<Event>) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let auth = self
+            .authentication_data
+            .read()
+            .expect("lock poisoned")
+            .clone();
+        let Some(auth) = auth else {
+            return;
+        };
+        if !auth.has_scope(scope) {
+            self.send_event(Event::ScopeRequired(scope.to_string()));
+            return;
+        }
+        self.status = format!("{name}...");
+        let event_sender = self.event_sender.clone();
+        let account = auth.name.clone();
+        let action = action(auth, event_sender.clone());
+        tokio::spawn(async move {
+            if let Err(err) = action.await {
+                error!("{name} failed: {err:#}");
+                if authentication::is_unauthorized(&err) {
+                    event_sender.send(Event::SessionExpired(account)).await.ok();
+                    return;
+                }
+                let message = format!("{name} failed: {err}");
+                event_sender.send(Event::ActionFailed(message)).await.ok();
+            }
+        });
+    }
+
+    fn send_event(&self, event: Event) {
+        if let Err(err) = self.event_sender.try_send(event) {
+            warn!("unable to send event: {err}");
+        }
+    }
+
+    /// Opens the edit history of the selected status if it has been edited.
+    fn show_history(&mut self) {
+        let Some(status) = self.selected_status() else {
+            return;
+        };
+        if status.edited_at.is_none() {
+            self.status = "This status has not been edited".to_string();
+            return;
+        }
+        self.send_event(Event::ShowHistory(status.id.clone()));
+    }
+
     fn update_status(&mut self, selected: usize) {
         if let Some(timeline) = &self.timeline {
             // let selected = Arc::clone(&self.list_state).read().map_or(0, |s|
             // s.selected().unwrap_or_default());
             if let Some(status) = timeline.get(selected) {
-                let date_format =
-                    format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]")
-                        .unwrap_or_default();
-                let date = status.created_at.format(&date_format).unwrap_or_default();
+                let date = status
+                    .created_at
+                    .with_timezone(&Local)
+                    .format_with_items(self.time_format.iter());
                 let url = status
                     .reblog
                     .as_ref()
@@ -135,6 +425,91 @@ impl Home {
     }
 }
 
+/// Fetches the newest statuses of a timeline, with the title to show above them.
+async fn fetch(
+    auth: &authentication::State,
+    source: &Timeline,
+    limit: u32,
+) -> Result<(String, Vec<Status>)> {
+    let server = auth.mastodon.data.base.trim_start_matches("https://");
+    let statuses = load(&*auth.client()?, server, source, limit).await?;
+    let title = match source {
+        Timeline::Home => format!("{}@{server}", auth.account.username),
+        Timeline::Tag(tag) => format!("#{tag}"),
+        Timeline::List(title) => format!("List: {title}"),
+        Timeline::User(acct) => format!("@{acct}"),
+    };
+    Ok((title, statuses))
+}
+
+/// Fetches the newest statuses of a timeline.
+async fn load(
+    client: &(dyn Megalodon + Send + Sync),
+    server: &str,
+    source: &Timeline,
+    limit: u32,
+) -> Result<Vec<Status>> {
+    let limit = Some(limit);
+    let response = match source {
+        Timeline::Home => {
+            let options = GetHomeTimelineInputOptions {
+                limit,
+                ..Default::default()
+            };
+            client.get_home_timeline(Some(&options)).await
+        }
+        Timeline::Tag(tag) => {
+            let options = GetTagTimelineInputOptions {
+                limit,
+                ..Default::default()
+            };
+            client.get_tag_timeline(tag.clone(), Some(&options)).await
+        }
+        Timeline::List(title) => {
+            let lists = client.get_lists().await.wrap_err("failed to load lists")?;
+            let list = lists
+                .json
+                .into_iter()
+                .find(|list| list.title.eq_ignore_ascii_case(title))
+                .ok_or_else(|| eyre!("no list named {title}"))?;
+            let options = GetListTimelineInputOptions {
+                limit,
+                ..Default::default()
+            };
+            client.get_list_timeline(list.id, Some(&options)).await
+        }
+        Timeline::User(acct) => {
+            // resolving finds accounts on other servers that this server hasn't seen yet
+            let options = SearchAccountInputOptions {
+                resolve: Some(true),
+                limit: Some(5),
+                ..Default::default()
+            };
+            let accounts = client
+                .search_account(acct.clone(), Some(&options))
+                .await
+                .wrap_err("failed to find account")?;
+            let account = accounts
+                .json
+                .into_iter()
+                .find(|account| {
+                    // accounts on the user's own server have no domain in `acct`
+                    account.acct.eq_ignore_ascii_case(acct)
+                        || format!("{}@{server}", account.acct).eq_ignore_ascii_case(acct)
+                })
+                .ok_or_else(|| eyre!("no account named @{acct}"))?;
+            let options = GetAccountStatusesInputOptions {
+                limit,
+                ..Default::default()
+            };
+            client
+                .get_account_statuses(account.id, Some(&options))
+                .await
+        }
+    };
+    Ok(response.wrap_err("failed to load timeline")?.json)
+}
+
 impl Widget for &Home {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let mut items = vec![];
@@ -145,14 +520,17 @@ impl Widget for &Home {
             // ));
             // items.push(ListItem::new(format!("{}", self.selected)));
             for status in timeline {
-                items.push(ListItem::new(format_status(status, area.width)));
+                items.push(ListItem::new(format_status(
+                    status,
+                    area.width,
+                    self.now,
+                    self.strip_emojis,
+                )));
             }
         } else {
             items.push(ListItem::new("Loading timeline..."));
         }
-        // this looks great on a dark theme, but not so much on a light one
-        let style = Style::default().bg(Color::Rgb(16, 32, 64));
-        let list = List::new(items).highlight_style(style);
+        let list = List::new(items).highlight_style(theme::current().highlight());
         // let mut state = ListState::default();
         // state.select(Some(self.selected));
         let list_state = Arc::clone(&self.list_state);
@@ -161,25 +539,149 @@ impl Widget for &Home {
     }
 }
 
-fn format_status(status: &Status, width: u16) -> Text {
+/// Formats a status for display: who boosted it, its author, what it replies to and its content
+/// wrapped to `width`.
+pub fn format_status(
+    status: &Status,
+    width: u16,
+    now: DateTime<Utc>,
+    strip_emojis: bool,
+) -> Text<'static> {
+    let mut text = Text::default();
+    // a reblog wraps the original status, so show who boosted it and then the original post
+    let original = if let Some(reblog) = &status.reblog {
+        text.push_line(boost_line(&status.account, strip_emojis));
+        reblog
+    } else {
+        status
+    };
+    text.push_line(header_line(original, now, strip_emojis));
+    if let Some(line) = reply_line(original) {
+        text.push_line(line);
+    }
+    // html2text wraps by char count, so render unwrapped and wrap by display width ourselves
+    let content = html2text::from_read(original.content.as_bytes(), UNWRAPPED_WIDTH).unwrap();
+    // shortcodes are found before wrapping, as a long one may be split across lines
+    let content = emoji::mark(&content, &original.emojis, strip_emojis);
+    let mut in_emoji = false;
+    for line in wrap::wrap(&content, width as usize) {
+        let spans = emoji::spans(&line.text, Style::default(), &mut in_emoji);
+        let alignment = if line.rtl {
+            Alignment::Right
+        } else {
+            Alignment::Left
+        };
+        text.push_line(Line::from(spans).alignment(alignment));
+    }
+    text.extend(Text::raw(""));
+    text
+}
+
+/// The "⟳ boosted by X" line shown above a reblogged status.
+fn boost_line(booster: &Account, strip_emojis: bool) -> Line<'static> {
+    let style = Style::default().fg(theme::current().link);
+    let italic = style.add_modifier(Modifier::ITALIC);
+    let mut spans = vec![Span::styled("⟳ boosted by ", style)];
+    spans.extend(display_name(booster, italic, strip_emojis));
+    spans.push(Span::styled(format!(" (@{})", booster.acct), italic));
+    Line::from(spans)
+}
+
+/// The author, relative age, edit age and visibility of a status.
+fn header_line(status: &Status, now: DateTime<Utc>, strip_emojis: bool) -> Line<'static> {
     let account = &status.account;
-    let reblog = status.reblog.as_ref();
-    let acct = reblog.map_or(account.acct.clone(), |reblog| reblog.account.acct.clone());
-    let display_name = reblog.map_or(account.display_name.clone(), |reblog| {
-        reblog.account.display_name.clone()
-    });
-    let mut text = Text::from(Line::from(vec![
-        Span::styled(format!("{acct} "), Style::default().fg(Color::Yellow)),
+    let theme = theme::current();
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let name_style = Style::default()
+        .fg(theme.name)
+        .add_modifier(Modifier::ITALIC);
+    let mut line = Line::from(vec![
         Span::styled(
-            format!("({display_name})"),
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::ITALIC),
+            format!("{} ", account.acct),
+            Style::default().fg(theme.accent),
         ),
-    ]));
-    let html = reblog.map_or(status.content.clone(), |reblog| reblog.content.clone());
-    let content = html2text::from_read(html.as_bytes(), width as usize).unwrap();
-    text.extend(Text::from(content));
-    text.extend(Text::raw(""));
-    text
+        Span::styled("(", name_style),
+    ]);
+    line.extend(display_name(account, name_style, strip_emojis));
+    line.extend([
+        Span::styled(")", name_style),
+        Span::styled(" · ", dim),
+        Span::styled(relative_age(status.created_at, now), dim),
+        Span::styled(" · ", dim),
+        Span::styled(visibility_icon(&status.visibility), dim),
+    ]);
+    if let Some(edited_at) = status.edited_at {
+        line.push_span(Span::styled(" · ", dim));
+        line.push_span(Span::styled(
+            format!("edited {} ago", relative_age(edited_at, now)),
+            dim.add_modifier(Modifier::ITALIC),
+        ));
+    }
+    line
+}
+
+/// The display name of an account with its custom emojis styled, or stripped for screen readers.
+fn display_name(account: &Account, style: Style, strip_emojis: bool) -> Vec<Span<'static>> {
+    if strip_emojis {
+        let name = emoji::strip(&account.display_name, &account.emojis, &account.username);
+        vec![Span::styled(name, style)]
+    } else {
+        emoji::emojify(&account.display_name, &account.emojis, style, false)
+    }
+}
+
+/// The "↩ in reply to @y" line for replies, or `None` if the status is not a reply.
+///
+/// The API only gives us the id of the account being replied to, so the acct is looked up from
+/// the status mentions (or the author for self-replies in a thread).
+fn reply_line(status: &Status) -> Option<Line<'static>> {
+    let account_id = status.in_reply_to_account_id.as_deref()?;
+    let acct = if status.account.id == account_id {
+        Some(status.account.acct.as_str())
+    } else {
+        status
+            .mentions
+            .iter()
+            .find(|mention| mention.id == account_id)
+            .map(|mention| mention.acct.as_str())
+    };
+    let text = acct.map_or_else(
+        || "↩ in reply to a post".to_string(),
+        |acct| format!("↩ in reply to @{acct}"),
+    );
+    Some(Line::styled(
+        text,
+        Style::default().fg(theme::current().reply),
+    ))
+}
+
+/// Formats the age of a timestamp relative to `now`, e.g. "42s", "3m", "2h", "5d", "Mar 4" or
+/// "Mar 4 2022" for timestamps from a previous year. Dates are shown in the local timezone.
+fn relative_age(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now.signed_duration_since(timestamp);
+    if age.num_minutes() < 1 {
+        format!("{}s", age.num_seconds().max(0))
+    } else if age.num_hours() < 1 {
+        format!("{}m", age.num_minutes())
+    } else if age.num_days() < 1 {
+        format!("{}h", age.num_hours())
+    } else if age.num_days() < 7 {
+        format!("{}d", age.num_days())
+    } else {
+        let timestamp = timestamp.with_timezone(&Local);
+        if timestamp.year() == now.with_timezone(&Local).year() {
+            timestamp.format("%b %-d").to_string()
+        } else {
+            timestamp.format("%b %-d %Y").to_string()
+        }
+    }
+}
+
+const fn visibility_icon(visibility: &StatusVisibility) -> &'static str {
+    match visibility {
+        StatusVisibility::Public => "🌐",
+        StatusVisibility::Unlisted => "🔓",
+        StatusVisibility::Private => "🔒",
+        StatusVisibility::Direct => "✉",
+    }
 }
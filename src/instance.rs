@@ -0,0 +1,303 @@
+//! Finds out what kind of server the user is signing in to before registering tooters with it.
+
+use std::time::Duration;
+
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::{debug, info};
+
+/// How long to wait for each request while probing a server
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Software that implements the Mastodon client API, as named in nodeinfo.
+const COMPATIBLE_SOFTWARE: &[&str] = &[
+    "mastodon",
+    "hometown",
+    "glitch-soc",
+    "fedibird",
+    "pleroma",
+    "akkoma",
+    "gotosocial",
+    "iceshrimp",
+    "sharkey",
+];
+
+/// What the server tells anonymous visitors about itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    /// The normalized server url, e.g. `https://mastodon.social`
+    pub base: String,
+    pub title: String,
+    pub version: String,
+    pub registrations: Registrations,
+    pub rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registrations {
+    Open,
+    ApprovalRequired,
+    Closed,
+    Unknown,
+}
+
+impl std::fmt::Display for Registrations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Open => "open",
+            Self::ApprovalRequired => "open, with approval",
+            Self::Closed => "closed",
+            Self::Unknown => "unknown",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Turns what the user typed into a server url: adds a missing scheme, strips any path, and
+/// accepts a handle such as `@user@mastodon.social` or `user@mastodon.social`.
+pub fn normalize_server_url(input: &str) -> Result<String> {
+    let input = input.trim();
+    let input = match input.rsplit_once('@') {
+        // a handle, but not a url with credentials or a path such as `mastodon.social/@user`
+        Some((_, host)) if !input.contains('/') => host,
+        _ => input,
+    };
+    if input.is_empty() {
+        bail!("enter the address of your server, e.g. mastodon.social");
+    }
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{input}")
+    };
+    let url = Url::parse(&with_scheme).wrap_err_with(|| format!("invalid server url {input}"))?;
+    if !matches!(url.scheme(), "https" | "http") {
+        bail!("unsupported server url scheme {}", url.scheme());
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| eyre!("no server in url {input}"))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}://{host}:{port}", url.scheme()),
+        None => format!("{}://{host}", url.scheme()),
+    })
+}
+
+/// Fetches information about the server at `base`, trying `/api/v2/instance`, then
+/// `/api/v1/instance`, then nodeinfo. Fails if the server does not support the Mastodon API.
+pub async fn probe(base: &str) -> Result<Instance> {
+    info!(base, "Probing instance");
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .wrap_err("unable to create http client")?;
+    match get_json::<InstanceV2>(&client, &format!("{base}/api/v2/instance")).await {
+        Ok(instance) => return Ok(instance.into_instance(base)),
+        Err(err) if is_unreachable(&err) => {
+            return Err(err).wrap_err_with(|| format!("unable to reach {base}"));
+        }
+        Err(err) => debug!("No v2 instance endpoint: {err:#}"),
+    }
+    match get_json::<InstanceV1>(&client, &format!("{base}/api/v1/instance")).await {
+        Ok(instance) => return Ok(instance.into_instance(base)),
+        Err(err) => debug!("No v1 instance endpoint: {err:#}"),
+    }
+    let nodeinfo = nodeinfo(&client, base)
+        .await
+        .wrap_err_with(|| format!("{base} does not look like a Mastodon server"))?;
+    let software = nodeinfo.software.name.to_lowercase();
+    if !COMPATIBLE_SOFTWARE.contains(&software.as_str()) {
+        bail!(
+            "{base} runs {} {}, which does not support Mastodon apps",
+            nodeinfo.software.name,
+            nodeinfo.software.version
+        );
+    }
+    Ok(nodeinfo.into_instance(base))
+}
+
+/// Whether the request failed before the server answered, e.g. because the host does not exist.
+fn is_unreachable(err: &color_eyre::Report) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<reqwest::Error>())
+        .any(|err| err.is_connect() || err.is_timeout())
+}
+
+async fn nodeinfo(client: &reqwest::Client, base: &str) -> Result<NodeInfo> {
+    let links: NodeInfoLinks = get_json(client, &format!("{base}/.well-known/nodeinfo")).await?;
+    let link = links
+        .links
+        .iter()
+        .rev()
+        .find(|link| link.rel.contains("nodeinfo.diaspora.software/ns/schema"))
+        .ok_or_else(|| eyre!("no nodeinfo schema link"))?;
+    get_json(client, &link.href).await
+}
+
+async fn get_json<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+    client
+        .get(url)
+        .send()
+        .await
+        .wrap_err_with(|| format!("request to {url} failed"))?
+        .error_for_status()?
+        .json()
+        .await
+        .wrap_err_with(|| format!("invalid response from {url}"))
+}
+
+/// The response of `GET /api/v2/instance` (Mastodon 4.0 and later).
+#[derive(Debug, Deserialize)]
+struct InstanceV2 {
+    title: String,
+    version: String,
+    registrations: RegistrationsV2,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistrationsV2 {
+    enabled: bool,
+    approval_required: bool,
+}
+
+/// The response of `GET /api/v1/instance`, also implemented by most Mastodon compatible servers.
+#[derive(Debug, Deserialize)]
+struct InstanceV1 {
+    title: String,
+    version: String,
+    #[serde(default)]
+    registrations: Option<bool>,
+    #[serde(default)]
+    approval_required: Option<bool>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeInfoLinks {
+    links: Vec<NodeInfoLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeInfoLink {
+    rel: String,
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeInfo {
+    software: Software,
+    open_registrations: Option<bool>,
+    #[serde(default)]
+    metadata: NodeInfoMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+struct Software {
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeInfoMetadata {
+    node_name: Option<String>,
+}
+
+impl InstanceV2 {
+    fn into_instance(self, base: &str) -> Instance {
+        let registrations = match self.registrations {
+            RegistrationsV2 { enabled: false, .. } => Registrations::Closed,
+            RegistrationsV2 {
+                approval_required: true,
+                ..
+            } => Registrations::ApprovalRequired,
+            _ => Registrations::Open,
+        };
+        Instance {
+            base: base.to_string(),
+            title: self.title,
+            version: self.version,
+            registrations,
+            rules: self.rules.into_iter().map(|rule| rule.text).collect(),
+        }
+    }
+}
+
+impl InstanceV1 {
+    fn into_instance(self, base: &str) -> Instance {
+        let registrations = match (self.registrations, self.approval_required) {
+            (Some(false), _) => Registrations::Closed,
+            (Some(true), Some(true)) => Registrations::ApprovalRequired,
+            (Some(true), _) => Registrations::Open,
+            (None, _) => Registrations::Unknown,
+        };
+        Instance {
+            base: base.to_string(),
+            title: self.title,
+            version: self.version,
+            registrations,
+            rules: self.rules.into_iter().map(|rule| rule.text).collect(),
+        }
+    }
+}
+
+impl NodeInfo {
+    fn into_instance(self, base: &str) -> Instance {
+        let registrations = match self.open_registrations {
+            Some(true) => Registrations::Open,
+            Some(false) => Registrations::Closed,
+            None => Registrations::Unknown,
+        };
+        Instance {
+            base: base.to_string(),
+            title: self.metadata.node_name.unwrap_or_else(|| base.to_string()),
+            version: format!("{} {}", self.software.name, self.software.version),
+            registrations,
+            rules: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_server_urls() {
+        let cases = [
+            ("mastodon.social", "https://mastodon.social"),
+            (" mastodon.social/ ", "https://mastodon.social"),
+            ("mastodon.social/about", "https://mastodon.social"),
+            ("mastodon.social/@alice", "https://mastodon.social"),
+            ("@alice@mastodon.social", "https://mastodon.social"),
+            ("alice@mastodon.social", "https://mastodon.social"),
+            ("https://mastodon.social/@alice", "https://mastodon.social"),
+            (
+                "https://mastodon.social/@alice@example.com",
+                "https://mastodon.social",
+            ),
+            ("localhost:3000", "https://localhost:3000"),
+            ("http://localhost:3000/", "http://localhost:3000"),
+            ("http://mastodon.local", "http://mastodon.local"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_server_url(input).unwrap(), expected, "{input}");
+        }
+        assert!(normalize_server_url("").is_err());
+        assert!(normalize_server_url("@alice@").is_err());
+        assert!(normalize_server_url("ftp://mastodon.social").is_err());
+    }
+}
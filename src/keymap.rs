@@ -0,0 +1,824 @@
+//! The keys that trigger actions, per view.
+//!
+//! Each view has a map from key sequences to named [`Action`]s, and the global map applies in every
+//! view that isn't a text input. The defaults are in [`Keymap::default`] and the `[keybindings]`
+//! settings replace the keys of individual actions. A sequence such as `g g` is a binding of several
+//! keys, and a count typed before a binding (e.g. `5 j`) repeats it.
+
+use std::{collections::BTreeMap, fmt};
+
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::settings;
+
+/// Something that the user can do with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Help,
+    CommandLine,
+    Palette,
+    Execute,
+    Complete,
+    Back,
+    Down,
+    Up,
+    Top,
+    Bottom,
+    ShowHistory,
+    Compose,
+    ShowAccounts,
+    Edit,
+    Delete,
+    Redraft,
+    Send,
+    CycleVisibility,
+    SwitchField,
+    Select,
+    AddAccount,
+    Logout,
+}
+
+impl Action {
+    /// The name of the action in the settings file.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Help => "help",
+            Self::CommandLine => "command_line",
+            Self::Palette => "palette",
+            Self::Execute => "execute",
+            Self::Complete => "complete",
+            Self::Back => "back",
+            Self::Down => "down",
+            Self::Up => "up",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::ShowHistory => "show_history",
+            Self::Compose => "compose",
+            Self::ShowAccounts => "show_accounts",
+            Self::Edit => "edit",
+            Self::Delete => "delete",
+            Self::Redraft => "redraft",
+            Self::Send => "send",
+            Self::CycleVisibility => "cycle_visibility",
+            Self::SwitchField => "switch_field",
+            Self::Select => "select",
+            Self::AddAccount => "add_account",
+            Self::Logout => "logout",
+        }
+    }
+
+    /// A short description for the status bar.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Help => "help",
+            Self::CommandLine => "command",
+            Self::Palette => "palette",
+            Self::Execute => "run",
+            Self::Complete => "complete",
+            Self::Back => "back",
+            Self::Down => "down",
+            Self::Up => "up",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::ShowHistory => "edit history",
+            Self::Compose => "new status",
+            Self::ShowAccounts => "accounts",
+            Self::Edit => "edit",
+            Self::Delete => "delete",
+            Self::Redraft => "delete and redraft",
+            Self::Send => "send",
+            Self::CycleVisibility => "visibility",
+            Self::SwitchField => "switch field",
+            Self::Select => "switch",
+            Self::AddAccount => "add account",
+            Self::Logout => "log out",
+        }
+    }
+
+    /// A sentence describing the action for the help overlay.
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Quit => "Quit tooters",
+            Self::Help => "Show the keys of this view",
+            Self::CommandLine => "Type a command, e.g. :tag rust",
+            Self::Palette => "Search the commands",
+            Self::Execute => "Run the command",
+            Self::Complete => "Complete the command or its argument",
+            Self::Back => "Go back to the previous view",
+            Self::Down => "Move down",
+            Self::Up => "Move up",
+            Self::Top => "Move to the top",
+            Self::Bottom => "Move to the bottom",
+            Self::ShowHistory => "Show the edit history of the selected status",
+            Self::Compose => "Compose a new status",
+            Self::ShowAccounts => "Show the accounts, to switch or add one",
+            Self::Edit => "Edit your selected status",
+            Self::Delete => "Delete your selected status",
+            Self::Redraft => "Delete your selected status and open it in the composer",
+            Self::Send => "Post the status",
+            Self::CycleVisibility => "Change who can see the status",
+            Self::SwitchField => "Switch between the content warning and the text",
+            Self::Select => "Switch to the selected account",
+            Self::AddAccount => "Sign in to another account",
+            Self::Logout => "Log out of the selected account and revoke its access",
+        }
+    }
+
+    /// The group that the help overlay lists the action in.
+    pub const fn category(self) -> Category {
+        match self {
+            Self::Quit
+            | Self::Help
+            | Self::CommandLine
+            | Self::Palette
+            | Self::Execute
+            | Self::Complete => Category::General,
+            Self::Back | Self::Down | Self::Up | Self::Top | Self::Bottom => Category::Navigation,
+            Self::ShowHistory
+            | Self::Compose
+            | Self::Edit
+            | Self::Delete
+            | Self::Redraft
+            | Self::Send
+            | Self::CycleVisibility
+            | Self::SwitchField => Category::Statuses,
+            Self::ShowAccounts | Self::Select | Self::AddAccount | Self::Logout => {
+                Category::Accounts
+            }
+        }
+    }
+}
+
+/// A group of related actions in the help overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    General,
+    Navigation,
+    Statuses,
+    Accounts,
+}
+
+impl Category {
+    pub const fn title(self) -> &'static str {
+        match self {
+            Self::General => "General",
+            Self::Navigation => "Navigation",
+            Self::Statuses => "Statuses",
+            Self::Accounts => "Accounts",
+        }
+    }
+}
+
+/// A set of bindings: the global bindings, or those of a view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Context {
+    Global,
+    Authentication,
+    Home,
+    History,
+    Composer,
+    Accounts,
+    /// The help overlay, which is shown over the other views
+    Help,
+    /// The command line and the command palette, which are shown over the other views
+    CommandLine,
+}
+
+impl Context {
+    /// The name of the context's table in the `[keybindings]` settings.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Authentication => "authentication",
+            Self::Home => "home",
+            Self::History => "history",
+            Self::Composer => "composer",
+            Self::Accounts => "accounts",
+            Self::Help => "help",
+            Self::CommandLine => "command_line",
+        }
+    }
+
+    /// The actions that can be bound in this context.
+    pub const fn actions(self) -> &'static [Action] {
+        use Action::*;
+        match self {
+            Self::Global => &[Quit, Help, CommandLine, Palette],
+            Self::Authentication => &[Back, Help],
+            Self::Home => &[
+                Down,
+                Up,
+                Top,
+                Bottom,
+                ShowHistory,
+                Compose,
+                ShowAccounts,
+                Edit,
+                Delete,
+                Redraft,
+            ],
+            Self::History => &[Back, Down, Up, Top],
+            Self::Composer => &[Back, Send, SwitchField, CycleVisibility, Help],
+            Self::Accounts => &[Back, Down, Up, Select, AddAccount, Logout],
+            Self::Help => &[Back, Down, Up],
+            Self::CommandLine => &[Back, Execute, Complete, Down, Up],
+        }
+    }
+
+    /// The actions shown in the status bar.
+    const fn hints(self) -> &'static [Action] {
+        use Action::*;
+        match self {
+            Self::Global => &[],
+            Self::Authentication => &[Help],
+            Self::Home => &[Quit, Help, CommandLine, Down, Up, Compose, ShowAccounts],
+            Self::History => &[Back, Help, Down, Up],
+            Self::Composer => &[Send, Back, Help, SwitchField, CycleVisibility],
+            Self::Accounts => &[Select, AddAccount, Logout, Back, Help],
+            Self::Help => &[Back, Down, Up],
+            Self::CommandLine => &[Execute, Complete, Back],
+        }
+    }
+
+    /// Views where the user types text don't use the global bindings or counts, so that letters
+    /// and digits reach the text input. The help overlay's text input is its search.
+    pub const fn is_text_input(self) -> bool {
+        matches!(
+            self,
+            Self::Authentication | Self::Composer | Self::Help | Self::CommandLine
+        )
+    }
+}
+
+/// A key with its modifiers. Shift is part of the character for character keys, so `G` and
+/// `shift-g` are the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers.difference(KeyModifiers::SHIFT),
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// The digit of a count, if this is a digit key without modifiers.
+    fn digit(self) -> Option<usize> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => {
+                c.to_digit(10).map(|digit| digit as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+/// Key names that aren't a single character, as written in the settings file.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("esc", KeyCode::Esc),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("space", KeyCode::Char(' ')),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl std::str::FromStr for Key {
+    type Err = color_eyre::Report;
+
+    /// Parses a key such as `j`, `G`, `esc`, `ctrl-s` or `alt-shift-up`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // the last part is the key, which may itself be `-`
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("{s:?} is not a key: unknown modifier {modifier:?}"),
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let name = rest.to_lowercase();
+                let function_key = name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                    .filter(|number| (1..=12).contains(number))
+                    .map(KeyCode::F);
+                KEY_NAMES
+                    .iter()
+                    .find(|(key_name, _)| *key_name == name)
+                    .map(|(_, code)| *code)
+                    .or(function_key)
+                    .ok_or_else(|| eyre!("{s:?} is not a key"))?
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl-"),
+            (KeyModifiers::ALT, "Alt-"),
+            (KeyModifiers::SHIFT, "Shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(number) => write!(f, "F{number}"),
+            KeyCode::Up => f.write_str("↑"),
+            KeyCode::Down => f.write_str("↓"),
+            KeyCode::Left => f.write_str("←"),
+            KeyCode::Right => f.write_str("→"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("Shift-Tab"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Delete => f.write_str("Del"),
+            KeyCode::Insert => f.write_str("Ins"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// A sequence of keys and the action that it triggers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub keys: Vec<Key>,
+    pub action: Action,
+}
+
+impl Binding {
+    fn parse(keys: &str, action: Action) -> Result<Self> {
+        let keys = keys
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Key>>>()?;
+        if keys.is_empty() {
+            bail!("empty key sequence");
+        }
+        Ok(Self { keys, action })
+    }
+
+    /// The keys as shown to the user, e.g. `g g` or `Ctrl-S`.
+    pub fn keys_text(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(Key::to_string).collect();
+        keys.join(" ")
+    }
+}
+
+/// The bindings of every context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: BTreeMap<Context, Vec<Binding>>,
+}
+
+/// The default keys of each action, per context
+type Defaults = &'static [(Context, &'static [(Action, &'static [&'static str])])];
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        let defaults: Defaults = &[
+            (
+                Context::Global,
+                &[
+                    (Quit, &["q"]),
+                    (Help, &["?", "f1"]),
+                    (CommandLine, &[":"]),
+                    (Palette, &["ctrl-p"]),
+                ],
+            ),
+            (
+                Context::Authentication,
+                &[(Back, &["esc"]), (Help, &["f1"])],
+            ),
+            (
+                Context::Home,
+                &[
+                    (Down, &["j", "down"]),
+                    (Up, &["k", "up"]),
+                    (Top, &["g g", "home"]),
+                    (Bottom, &["G", "end"]),
+                    (ShowHistory, &["H"]),
+                    (Compose, &["n"]),
+                    (ShowAccounts, &["a"]),
+                    (Edit, &["e"]),
+                    (Delete, &["d"]),
+                    (Redraft, &["D"]),
+                ],
+            ),
+            (
+                Context::History,
+                &[
+                    (Back, &["esc"]),
+                    (Down, &["j", "down"]),
+                    (Up, &["k", "up"]),
+                    (Top, &["g g", "home"]),
+                ],
+            ),
+            (
+                Context::Composer,
+                &[
+                    (Back, &["esc"]),
+                    (Send, &["ctrl-s"]),
+                    (SwitchField, &["tab", "backtab"]),
+                    (CycleVisibility, &["ctrl-t"]),
+                    (Help, &["f1"]),
+                ],
+            ),
+            (
+                Context::Accounts,
+                &[
+                    (Back, &["esc"]),
+                    (Down, &["j", "down"]),
+                    (Up, &["k", "up"]),
+                    (Select, &["enter"]),
+                    (AddAccount, &["n"]),
+                    (Logout, &["x"]),
+                ],
+            ),
+            (
+                Context::Help,
+                &[
+                    (Back, &["esc"]),
+                    (Down, &["down", "pagedown"]),
+                    (Up, &["up", "pageup"]),
+                ],
+            ),
+            (
+                Context::CommandLine,
+                &[
+                    (Back, &["esc"]),
+                    (Execute, &["enter"]),
+                    (Complete, &["tab"]),
+                    (Down, &["down", "ctrl-n"]),
+                    (Up, &["up", "ctrl-p"]),
+                ],
+            ),
+        ];
+        let bindings = defaults
+            .iter()
+            .map(|(context, actions)| {
+                let bindings = actions
+                    .iter()
+                    .flat_map(|(action, keys)| {
+                        keys.iter().map(|keys| {
+                            Binding::parse(keys, *action).expect("default keys are valid")
+                        })
+                    })
+                    .collect();
+                (*context, bindings)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The default bindings, with the keys of the actions in the settings replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the setting if a key is not valid or an action can't be bound in
+    /// that view.
+    pub fn new(settings: &settings::Keybindings) -> Result<Self> {
+        let mut keymap = Self::default();
+        for (context, overrides) in settings.tables() {
+            let bindings = keymap.bindings.entry(context).or_default();
+            for (action, keys) in overrides {
+                let name = format!("keybindings.{}.{}", context.name(), action.name());
+                if !context.actions().contains(action) {
+                    bail!(
+                        "invalid key {name}: {} is not an action in the {} view",
+                        action.name(),
+                        context.name()
+                    );
+                }
+                bindings.retain(|binding| binding.action != *action);
+                for keys in keys.as_slice() {
+                    let binding = Binding::parse(keys, *action)
+                        .wrap_err_with(|| format!("invalid value for {name}"))?;
+                    bindings.push(binding);
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// The bindings of a context, in the order they were defined.
+    pub fn bindings(&self, context: Context) -> &[Binding] {
+        self.bindings.get(&context).map_or(&[], Vec::as_slice)
+    }
+
+    /// The status bar hints of a view: the first keys of its main actions, and their labels.
+    pub fn hints(&self, context: Context) -> Vec<(String, &'static str)> {
+        context
+            .hints()
+            .iter()
+            .filter_map(|action| {
+                let binding = self
+                    .bindings(context)
+                    .iter()
+                    .chain(self.bindings(Context::Global))
+                    .find(|binding| binding.action == *action)?;
+                Some((binding.keys_text(), action.label()))
+            })
+            .collect()
+    }
+
+    /// Every action that can be used in a view and the bindings that trigger it, grouped by
+    /// category. Actions that the user has unbound have no bindings.
+    pub fn actions(&self, context: Context) -> Vec<(Action, Vec<&Binding>)> {
+        let contexts = Self::contexts(context);
+        let mut actions: Vec<Action> = contexts
+            .iter()
+            .flat_map(|context| context.actions())
+            .copied()
+            .collect();
+        actions.sort_by_key(|action| (action.category(), *action));
+        actions.dedup();
+        actions
+            .into_iter()
+            .map(|action| {
+                // the bindings of the view take precedence over global ones
+                let bindings = contexts
+                    .iter()
+                    .map(|context| {
+                        self.bindings(*context)
+                            .iter()
+                            .filter(|binding| binding.action == action)
+                            .collect::<Vec<_>>()
+                    })
+                    .find(|bindings| !bindings.is_empty())
+                    .unwrap_or_default();
+                (action, bindings)
+            })
+            .collect()
+    }
+
+    /// The contexts whose bindings apply in a view, most specific first.
+    fn contexts(context: Context) -> Vec<Context> {
+        if context.is_text_input() {
+            vec![context]
+        } else {
+            vec![context, Context::Global]
+        }
+    }
+}
+
+/// What a key press means once the keys before it are taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// A binding is complete: run the action `count` times
+    Action(Action, usize),
+    /// The key is part of a count or of a sequence that isn't complete yet
+    Pending,
+    /// No binding uses the key, so the view can handle it itself
+    Unbound,
+}
+
+/// Turns key presses into actions, keeping track of counts and incomplete sequences.
+#[derive(Debug, Default)]
+pub struct Keys {
+    keymap: Keymap,
+    count: Option<usize>,
+    pending: Vec<Key>,
+}
+
+impl Keys {
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            count: None,
+            pending: vec![],
+        }
+    }
+
+    pub const fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Whether a count or an incomplete sequence is waiting for more keys.
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || !self.pending.is_empty()
+    }
+
+    /// The count and keys typed so far, e.g. `5` or `g`, to show while waiting for more keys.
+    pub fn pending_text(&self) -> String {
+        let count = self.count.map(|count| count.to_string());
+        let keys = self.pending.iter().map(Key::to_string);
+        count.into_iter().chain(keys).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Resolves a key press in a view. Bindings of the view take precedence over global bindings,
+    /// and a complete binding wins over longer sequences that start with the same keys.
+    pub fn resolve(&mut self, context: Context, key: impl Into<Key>) -> Resolution {
+        let key = key.into();
+        let contexts = Keymap::contexts(context);
+        let bindings = || {
+            contexts
+                .iter()
+                .flat_map(|context| self.keymap.bindings(*context))
+        };
+        if key.code == KeyCode::Esc && self.is_pending() {
+            self.reset();
+            return Resolution::Pending;
+        }
+        if let Some(digit) = key.digit() {
+            let bound = bindings().any(|binding| binding.keys[0] == key);
+            let leading_zero = digit == 0 && self.count.is_none();
+            if self.pending.is_empty() && !context.is_text_input() && !bound && !leading_zero {
+                self.count = Some(
+                    self.count
+                        .unwrap_or_default()
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+                return Resolution::Pending;
+            }
+        }
+        self.pending.push(key);
+        let mut candidates = bindings().filter(|binding| binding.keys.starts_with(&self.pending));
+        let Some(first) = candidates.next() else {
+            let broke_sequence = self.pending.len() > 1;
+            self.reset();
+            // the key that broke a sequence may start a new one, e.g. `g` then `j` moves down
+            if broke_sequence {
+                return self.resolve(context, key);
+            }
+            return Resolution::Unbound;
+        };
+        let complete = std::iter::once(first)
+            .chain(candidates)
+            .find(|binding| binding.keys == self.pending);
+        match complete {
+            Some(binding) => {
+                let resolution = Resolution::Action(binding.action, self.count.unwrap_or(1));
+                self.reset();
+                resolution
+            }
+            None => Resolution::Pending,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(key("j"), Key::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        assert_eq!(key("G"), key("shift-g"));
+        assert_eq!(
+            key("ctrl-s"),
+            Key::new(KeyCode::Char('s'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            key("ctrl--"),
+            Key::new(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            key("PageDown"),
+            Key::new(KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert_eq!(key("f5"), Key::new(KeyCode::F(5), KeyModifiers::NONE));
+        assert!("hyper-x".parse::<Key>().is_err());
+        assert!("jj".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn shows_keys() {
+        assert_eq!(key("ctrl-s").to_string(), "Ctrl-S");
+        assert_eq!(key("esc").to_string(), "Esc");
+        assert_eq!(key("pagedown").to_string(), "PgDn");
+        assert_eq!(key("down").to_string(), "↓");
+    }
+
+    #[test]
+    fn resolves_sequences_and_counts() {
+        let mut keys = Keys::default();
+        assert_eq!(keys.resolve(Context::Home, key("g")), Resolution::Pending);
+        assert_eq!(
+            keys.resolve(Context::Home, key("g")),
+            Resolution::Action(Action::Top, 1)
+        );
+        assert_eq!(keys.resolve(Context::Home, key("1")), Resolution::Pending);
+        assert_eq!(keys.resolve(Context::Home, key("2")), Resolution::Pending);
+        assert_eq!(keys.pending_text(), "12");
+        assert_eq!(
+            keys.resolve(Context::Home, key("j")),
+            Resolution::Action(Action::Down, 12)
+        );
+        assert_eq!(
+            keys.resolve(Context::Home, key("q")),
+            Resolution::Action(Action::Quit, 1)
+        );
+        assert_eq!(keys.resolve(Context::Home, key("z")), Resolution::Unbound);
+        assert_eq!(keys.resolve(Context::Home, key("g")), Resolution::Pending);
+        assert_eq!(
+            keys.resolve(Context::Home, key("j")),
+            Resolution::Action(Action::Down, 1)
+        );
+        assert_eq!(keys.resolve(Context::Home, key("g")), Resolution::Pending);
+        assert_eq!(keys.resolve(Context::Home, key("z")), Resolution::Unbound);
+        assert_eq!(keys.pending_text(), "");
+    }
+
+    #[test]
+    fn text_inputs_get_letters_and_digits() {
+        let mut keys = Keys::default();
+        assert_eq!(
+            keys.resolve(Context::Composer, key("q")),
+            Resolution::Unbound
+        );
+        assert_eq!(
+            keys.resolve(Context::Composer, key("5")),
+            Resolution::Unbound
+        );
+        assert_eq!(
+            keys.resolve(Context::Composer, key("ctrl-s")),
+            Resolution::Action(Action::Send, 1)
+        );
+    }
+
+    #[test]
+    fn settings_replace_default_keys() {
+        let settings: settings::Keybindings =
+            toml::from_str("[home]\ndown = \"ctrl-n\"\ntop = [\"t\", \"g g\"]\n").unwrap();
+        let mut keys = Keys::new(Keymap::new(&settings).unwrap());
+        assert_eq!(keys.resolve(Context::Home, key("j")), Resolution::Unbound);
+        assert_eq!(
+            keys.resolve(Context::Home, key("ctrl-n")),
+            Resolution::Action(Action::Down, 1)
+        );
+        assert_eq!(
+            keys.resolve(Context::Home, key("t")),
+            Resolution::Action(Action::Top, 1)
+        );
+        let settings: settings::Keybindings = toml::from_str("[home]\nsend = \"s\"\n").unwrap();
+        assert!(Keymap::new(&settings).is_err());
+    }
+}
@@ -1,6 +1,13 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    sync::{Arc, LazyLock, OnceLock, RwLock},
+};
 
-use color_eyre::{eyre::WrapErr, Result};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
@@ -8,6 +15,7 @@ use ratatui::{
     text::{Line, Text},
     widgets::{Block, Borders, Paragraph, Widget, Wrap},
 };
+use regex::Regex;
 use tracing::{
     field::{Field, Visit},
     metadata::LevelFilter,
@@ -15,12 +23,26 @@ use tracing::{
 };
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{
-    fmt, layer::Context, prelude::*, registry::LookupSpan, EnvFilter, Layer, Registry,
+    field::MakeExt,
+    fmt::{self, format, MakeWriter},
+    layer::Context,
+    prelude::*,
+    registry::LookupSpan,
+    reload, EnvFilter, Layer, Registry,
 };
 use xdg::BaseDirectories;
 
+use crate::{settings, theme};
+
+/// Swaps the log filter when the settings file changes. Unset if `RUST_LOG` was used, as it takes
+/// precedence over the settings file (but not over `--log-level`).
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
 /// Sets up logging to a file and a collector for the logs that can be used to display them in the
-/// UI.
+/// UI. Secrets such as access tokens and auth codes are redacted from both.
+///
+/// The filter comes from `--log-level` if `from_flag` is set, which the settings then contain, or
+/// else from `RUST_LOG`, or else from the settings file.
 ///
 /// Returns a tuple containing the logs and a `WorkerGuard` which ensures that buffered logs are
 /// flushed to their output in the case of abrupt terminations of a process.
@@ -29,20 +51,28 @@ use xdg::BaseDirectories;
 ///
 /// Returns an error if the environment filter could not be built or if the XDG base directories
 /// could not be retrieved.
-pub fn init() -> Result<(LogCollector, WorkerGuard)> {
-    let env_filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env()
-        .wrap_err("failed to build env filter")?;
+pub fn init(settings: &settings::Logging, from_flag: bool) -> Result<(LogCollector, WorkerGuard)> {
+    // RUST_LOG takes precedence over the level in the settings file, but not over the flag
+    let from_env = !from_flag && std::env::var_os(EnvFilter::DEFAULT_ENV).is_some();
+    let env_filter = if from_env {
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env()
+            .wrap_err("failed to build env filter")?
+    } else {
+        settings.filter()?
+    };
+    let (env_filter, filter_handle) = reload::Layer::new(env_filter);
+    if !from_env {
+        FILTER.set(filter_handle).ok();
+    }
 
     let log_folder = BaseDirectories::with_prefix("tooters")
         .wrap_err("failed to get XDG base directories")?
         .get_state_home(); // usually this will be ~/.local/state/tooters
     let file_appender = tracing_appender::rolling::hourly(log_folder, "tooters.log");
     let (file_appender, guard) = tracing_appender::non_blocking(file_appender);
-    let file_layer = fmt::layer()
-        .with_writer(file_appender)
-        .with_timer(tracing_subscriber::fmt::time::uptime());
+    let file_layer = file_layer(file_appender);
 
     let log_collector = LogCollector::default();
 
@@ -57,6 +87,39 @@ pub fn init() -> Result<(LogCollector, WorkerGuard)> {
     Ok((log_collector, guard))
 }
 
+/// Replaces the log filter with the level from the settings, unless `RUST_LOG` is set.
+///
+/// # Errors
+///
+/// Returns an error if the level is not a valid filter, or if `RUST_LOG` is set.
+pub fn set_filter(settings: &settings::Logging) -> Result<()> {
+    let Some(handle) = FILTER.get() else {
+        bail!("RUST_LOG is set, so the log level can't be changed");
+    };
+    handle
+        .reload(settings.filter()?)
+        .wrap_err("failed to replace the log filter")
+}
+
+/// The layer that writes logs to `writer`, with secrets redacted.
+fn file_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    // fields named like secrets are redacted whole, as their values may contain separators
+    let fields = format::debug_fn(|writer, field, value| match field.name() {
+        "message" => write!(writer, "{value:?}"),
+        name if is_secret_name(name) => write!(writer, "{name}={REDACTED}"),
+        name => write!(writer, "{name}={value:?}"),
+    })
+    .delimited(" ");
+    fmt::layer()
+        .fmt_fields(fields)
+        .with_writer(Redacting(writer))
+        .with_timer(tracing_subscriber::fmt::time::uptime())
+}
+
 /// Thread-safe log collector
 #[derive(Debug, Default, Clone)]
 pub struct LogCollector {
@@ -115,7 +178,7 @@ impl LogMessage {
         }
     }
 
-    fn to_line(&self) -> Line {
+    fn to_line(&self) -> Line<'_> {
         Line::from_iter([
             self.level.as_str().fg(level_color(self.level)),
             " ".into(),
@@ -140,10 +203,15 @@ impl From<&Event<'_>> for LogMessage {
 impl Visit for LogMessage {
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         if field.name() == "message" {
-            self.message = format!("{value:?}");
-        } else {
+            self.message = redact(&format!("{value:?}")).into_owned();
+        } else if is_secret_name(field.name()) {
             self.fields
-                .push((field.name().to_string(), format!("{value:?}")));
+                .push((field.name().to_string(), REDACTED.to_string()));
+        } else {
+            self.fields.push((
+                field.name().to_string(),
+                redact(&format!("{value:?}")).into_owned(),
+            ));
         }
     }
 }
@@ -161,12 +229,217 @@ impl Widget for &LogCollector {
     }
 }
 
-const fn level_color(level: Level) -> Color {
+fn level_color(level: Level) -> Color {
+    let theme = theme::current();
     match level {
-        Level::ERROR => Color::Red,
-        Level::WARN => Color::Yellow,
-        Level::INFO => Color::Green,
-        Level::DEBUG => Color::Blue,
-        Level::TRACE => Color::Cyan,
+        Level::ERROR => theme.error,
+        Level::WARN => theme.warning,
+        Level::INFO => theme.success,
+        Level::DEBUG => theme.link,
+        Level::TRACE => theme.muted,
+    }
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Names of values that must not be logged, as they would let anyone who reads the logs act as the
+/// user. Plain `code` is left out as it often means a status code; see [`AUTH_CODE`].
+const SECRET_NAMES: &str = r"access_token|refresh_token|token|client_secret|secret|code_verifier|auth(?:orization)?[ _]code|password|passphrase";
+
+/// A value: quoted, or up to the next separator.
+const VALUE: &str = r#""(?:[^"\\]|\\.)*"|'[^']*'|[^\s"'&,;)}\]]+"#;
+
+/// A secret name followed by its value, e.g. `token: "..."` (debug output), `token=...` (fields
+/// and query strings), `"token": "..."` (JSON) or `Auth code: ...` (messages).
+static SECRET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"(?i)\b(?P<name>{SECRET_NAMES})(?P<separator>"?\s*[:=]\s*)(?:{VALUE})"#
+    ))
+    .expect("valid regex")
+});
+
+/// An authorization code in a callback url or a token request: `code=...` or `"code": "..."`.
+static AUTH_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"\b(?P<name>code)(?P<separator>=|"\s*:\s*)(?:{VALUE})"#
+    ))
+    .expect("valid regex")
+});
+
+/// A token in an `Authorization` header.
+static BEARER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(?P<name>bearer)(?P<separator>\s+)(?:{VALUE})"
+    ))
+    .expect("valid regex")
+});
+
+/// Masks the access tokens, client secrets, auth codes and passphrases in `text`.
+fn redact(text: &str) -> Cow<'_, str> {
+    let replacement = format!("${{name}}${{separator}}{REDACTED}");
+    let mut text = Cow::Borrowed(text);
+    for regex in [&*SECRET, &*AUTH_CODE, &*BEARER] {
+        if let Cow::Owned(redacted) = regex.replace_all(&text, replacement.as_str()) {
+            text = Cow::Owned(redacted);
+        }
+    }
+    text
+}
+
+/// Whether a recorded field holds a secret, going by its name, e.g. `token` or `client_secret`.
+fn is_secret_name(name: &str) -> bool {
+    static NAME: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(&format!(r"(?i)^(?:{SECRET_NAMES}|code)$")).expect("valid regex")
+    });
+    NAME.is_match(name)
+}
+
+/// Makes writers that redact secrets from what is written to them.
+struct Redacting<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// Redacts secrets from each write. The fmt layer writes each event in a single call, so secrets
+/// are never split across writes.
+struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use mastodon_async::{data::Data, Mastodon};
+    use tracing::{debug, info, warn};
+
+    use super::*;
+
+    /// A log sink that keeps what is written to it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    /// Logs credentials in the ways that the app has (or could have) logged them.
+    fn log_credentials() {
+        let mastodon = Mastodon::from(Data {
+            base: "https://mastodon.example".into(),
+            client_id: "client-id".into(),
+            client_secret: "SECRET-client".into(),
+            redirect: "urn:ietf:wg:oauth:2.0:oob".into(),
+            token: "SECRET-token".into(),
+        });
+        debug!("Mastodon: {:?}", mastodon);
+        debug!("Auth code: {}", "SECRET-code");
+        info!("Callback: http://127.0.0.1:8080/callback?code=SECRET-query&state=abc");
+        debug!(r#"Response: {{"access_token":"SECRET-json","token_type":"Bearer"}}"#);
+        warn!("Request failed: Authorization: Bearer SECRET-bearer");
+        info!(token = "SECRET-field", "Signed in");
+        info!(passphrase = %"SECRET with spaces", "Unlocked");
+        info!(client_secret = ?"SECRET-debug", "Registered");
+    }
+
+    fn assert_redacted(logs: &str) {
+        assert!(!logs.contains("SECRET"), "credentials in logs: {logs}");
+        assert!(logs.contains(REDACTED), "nothing redacted: {logs}");
+    }
+
+    #[test]
+    fn no_credentials_reach_either_sink() {
+        let file = SharedBuffer::default();
+        let collector = LogCollector::default();
+        let writer = file.clone();
+        let subscriber = Registry::default()
+            .with(file_layer(move || writer.clone()))
+            .with(collector.clone());
+        tracing::subscriber::with_default(subscriber, log_credentials);
+
+        assert_redacted(&file.contents());
+        let logs = collector.last_n(usize::MAX);
+        assert_eq!(logs.len(), 8);
+        let collected = logs
+            .iter()
+            .map(|log| format!("{} {:?}", log.message, log.fields))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_redacted(&collected);
+    }
+
+    #[test]
+    fn keeps_the_rest_of_the_message() {
+        let file = SharedBuffer::default();
+        let writer = file.clone();
+        let subscriber = Registry::default().with(file_layer(move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, log_credentials);
+
+        let logs = file.contents();
+        assert!(logs.contains("base: \"https://mastodon.example\""));
+        assert!(logs.contains("state=abc"));
+        assert!(logs.contains("\"token_type\":\"Bearer\""));
+        assert!(logs.contains("Signed in token=[REDACTED]"));
+    }
+
+    #[test]
+    fn redacts_secrets_in_text() {
+        let cases = [
+            ("token: \"abc\"", "token: [REDACTED]"),
+            ("client_secret=abc&x=1", "client_secret=[REDACTED]&x=1"),
+            ("\"access_token\": \"abc\"", "\"access_token\": [REDACTED]"),
+            ("Auth code: abc", "Auth code: [REDACTED]"),
+            ("?code=abc&state=1", "?code=[REDACTED]&state=1"),
+            (
+                "Authorization: Bearer abc",
+                "Authorization: Bearer [REDACTED]",
+            ),
+            ("code_verifier=abc", "code_verifier=[REDACTED]"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(redact(text), expected, "redacting {text:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        for text in [
+            "status code: 401",
+            "Revoked access token",
+            "Loaded 3 tokens",
+            "token_type=Bearer",
+        ] {
+            assert!(
+                matches!(redact(text), Cow::Borrowed(_)),
+                "redacted {text:?}"
+            );
+        }
     }
 }
@@ -1,21 +1,41 @@
-use app::App;
+use clap::Parser;
+use cli::Cli;
 use color_eyre::eyre::Context;
+use settings::Settings;
 
+mod accounts;
+mod api;
 mod app;
 mod authentication;
+mod cli;
+mod command;
+mod command_line;
+mod composer;
 mod config;
+mod crypto;
+mod emoji;
 mod event;
+mod help;
+mod history;
 mod home;
+mod instance;
+mod keymap;
 pub mod logging;
+mod oauth;
 mod root;
+mod settings;
+mod theme;
 mod widgets;
+mod wrap;
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
+    let cli = Cli::parse();
     color_eyre::install()?;
-    let (logs, _guard) = logging::init().wrap_err("logging init failed")?;
-    let terminal = ratatui::init();
-    let result = App::new(logs).run(terminal).await;
-    ratatui::restore();
-    result
+    cli.use_config_dir();
+    let mut settings = Settings::load()?;
+    cli.overrides.apply(&mut settings)?;
+    let (logs, _guard) = logging::init(&settings.logging, cli.overrides.log_level.is_some())
+        .wrap_err("logging init failed")?;
+    cli.run(settings, logs).await
 }
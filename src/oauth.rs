@@ -0,0 +1,233 @@
+//! The parts of OAuth that `mastodon_async` does not support: PKCE, the `state` parameter,
+//! exchanging the code for a token with a code verifier and revoking tokens.
+
+use std::{fmt, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::{eyre::WrapErr, Result};
+use mastodon_async::{data::Data, registration::Registered, Mastodon};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How long to wait for the server to revoke a token
+const REVOKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A client app registered with a server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientApp {
+    pub base: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Every redirect uri registered for the app, e.g. the out-of-band uri and a callback url
+    pub redirect_uris: Vec<String>,
+    /// The space separated scopes that the app may ask for
+    pub scopes: String,
+}
+
+impl From<Registered> for ClientApp {
+    fn from(registered: Registered) -> Self {
+        let (base, client_id, client_secret, redirect_uris, scopes, _force_login) =
+            registered.into_parts();
+        Self {
+            base,
+            client_id,
+            client_secret,
+            redirect_uris: redirect_uris.split_whitespace().map(String::from).collect(),
+            scopes: scopes.to_string(),
+        }
+    }
+}
+
+/// A single authorization attempt: where the server sends the code, which scopes it asks for and
+/// the secrets that tie the code to this attempt.
+#[derive(Debug)]
+pub struct AuthorizationRequest {
+    pub redirect_uri: String,
+    /// The space separated scopes to ask for
+    pub scopes: String,
+    /// Returned unchanged in the callback, which shows that the callback answers this attempt
+    pub state: String,
+    /// Sent with the token request, which shows that it comes from the client that asked for the
+    /// code (PKCE)
+    code_verifier: String,
+}
+
+impl AuthorizationRequest {
+    pub fn new(redirect_uri: String, scopes: String) -> Self {
+        Self {
+            redirect_uri,
+            scopes,
+            state: random_string(32),
+            code_verifier: random_string(64),
+        }
+    }
+
+    fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Whether the space separated `granted` scopes include `scope`, either directly or through its
+/// parent scope (e.g. `write` grants `write:statuses`).
+pub fn grants(granted: &str, scope: &str) -> bool {
+    let parent = scope.split_once(':').map_or(scope, |(parent, _)| parent);
+    granted
+        .split_whitespace()
+        .any(|granted| granted == scope || granted == parent)
+}
+
+impl ClientApp {
+    /// Whether the app may ask for all of the space separated `scopes`.
+    pub fn allows(&self, scopes: &str) -> bool {
+        scopes
+            .split_whitespace()
+            .all(|scope| grants(&self.scopes, scope))
+    }
+
+    /// The port of the registered local callback url, if any.
+    pub fn callback_port(&self) -> Option<u16> {
+        self.redirect_uris
+            .iter()
+            .filter_map(|uri| Url::parse(uri).ok())
+            .find(|url| url.host_str() == Some("127.0.0.1"))
+            .and_then(|url| url.port())
+    }
+
+    /// The url that the user opens to authorize tooters.
+    pub fn authorize_url(&self, request: &AuthorizationRequest) -> Result<String> {
+        let mut url = Url::parse(&self.base)
+            .and_then(|base| base.join("/oauth/authorize"))
+            .wrap_err_with(|| format!("invalid server url {}", self.base))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &request.redirect_uri)
+            .append_pair("scope", &request.scopes)
+            .append_pair("state", &request.state)
+            .append_pair("code_challenge", &request.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Exchanges the code from the authorization callback for an access token. Returns the client
+    /// and the scopes that the token grants.
+    pub async fn exchange_code(
+        &self,
+        request: &AuthorizationRequest,
+        code: &str,
+    ) -> Result<(Mastodon, String)> {
+        let url = format!("{}/oauth/token", self.base);
+        let response = reqwest::Client::new()
+            .post(&url)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+                ("redirect_uri", &request.redirect_uri),
+                ("code_verifier", &request.code_verifier),
+            ])
+            .send()
+            .await
+            .wrap_err_with(|| format!("request to {url} failed"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let error = response
+                .json::<ErrorResponse>()
+                .await
+                .map(|response| response.error)
+                .ok();
+            return Err(TokenError {
+                status: status.as_u16(),
+                error,
+            })
+            .wrap_err("the server rejected the auth code");
+        }
+        let token: Token = response
+            .json()
+            .await
+            .wrap_err_with(|| format!("invalid response from {url}"))?;
+        let mastodon = Mastodon::from(Data {
+            base: self.base.clone().into(),
+            client_id: self.client_id.clone().into(),
+            client_secret: self.client_secret.clone().into(),
+            redirect: request.redirect_uri.clone().into(),
+            token: token.access_token.into(),
+        });
+        // servers only leave out the scope when they granted everything that was asked for
+        let scopes = token.scope.unwrap_or_else(|| request.scopes.clone());
+        Ok((mastodon, scopes))
+    }
+}
+
+/// The response to a token request.
+#[derive(Debug, Deserialize)]
+struct Token {
+    access_token: String,
+    scope: Option<String>,
+}
+
+/// The body of an OAuth error response.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// A token request that the server turned down, with the OAuth error code if it sent one, e.g.
+/// `invalid_grant` for a mistyped auth code.
+#[derive(Debug)]
+pub struct TokenError {
+    pub status: u16,
+    pub error: Option<String>,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            Some(error) => write!(f, "{error} (HTTP {})", self.status),
+            None => write!(f, "HTTP {}", self.status),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Whether `err` was caused by the server not knowing the client app, e.g. because it was removed
+/// from the server, rather than by the auth code or the network.
+pub fn is_rejected_client(err: &color_eyre::Report) -> bool {
+    err.chain().any(|err| {
+        err.downcast_ref::<TokenError>()
+            .is_some_and(|err| err.status == 401 || err.error.as_deref() == Some("invalid_client"))
+    })
+}
+
+/// Revokes the access token in `data`, so that it can no longer be used even if it is leaked.
+pub async fn revoke_token(data: &Data) -> Result<()> {
+    let url = format!("{}/oauth/revoke", data.base);
+    // the app waits for this when logging out, so a slow server must not hang it
+    reqwest::Client::builder()
+        .timeout(REVOKE_TIMEOUT)
+        .build()?
+        .post(&url)
+        .form(&[
+            ("client_id", &*data.client_id),
+            ("client_secret", &*data.client_secret),
+            ("token", &*data.token),
+        ])
+        .send()
+        .await
+        .wrap_err_with(|| format!("request to {url} failed"))?
+        .error_for_status()
+        .wrap_err("the server refused to revoke the token")?;
+    Ok(())
+}
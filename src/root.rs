@@ -1,70 +1,532 @@
 use std::sync::{Arc, RwLock};
 
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use crossterm::event::Event as CrosstermEvent;
 use ratatui::prelude::*;
 use tokio::sync::mpsc::Sender;
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
 
 use crate::{
-    authentication::Authentication,
+    accounts::Accounts,
+    authentication::{self, Authentication, Login},
+    command::Command,
+    command_line::{CommandLine, Mode},
+    composer::Composer,
+    config::Config,
     event::{Event, Outcome},
+    help::Help,
+    history::History,
     home::Home,
-    logging::LogCollector,
-    widgets::{StatusBar, TitleBar},
+    keymap::{Action, Context, Keymap, Keys, Resolution},
+    logging::{self, LogCollector},
+    settings::Settings,
+    theme::{self, Theme},
+    widgets::{StatusBar, TitleBar, Toast},
 };
 
+#[derive(Clone, Copy)]
 enum State {
     Authentication,
     Home,
+    History,
+    Composer,
+    Accounts,
 }
 
 pub struct Root {
-    _event_sender: Sender<Event>,
+    event_sender: Sender<Event>,
     state: State,
+    authentication_data: Arc<RwLock<Option<authentication::State>>>,
     authentication: Authentication,
     home: Home,
+    history: History,
+    composer: Composer,
+    accounts: Accounts,
+    /// The view to return to, and the account that was logged in, while signing in again
+    resume: Option<(State, String)>,
     logs: LogCollector,
     show_logs: bool,
+    settings: Settings,
+    /// Turns key presses into actions
+    keys: Keys,
+    /// The keys of the current view, shown over it
+    help: Option<Help>,
+    command_line: CommandLine,
+    /// A message about something that happened outside the current view, e.g. reloaded settings
+    toast: Option<Toast>,
 }
 
 /// The root component is the top-level component of the application.
 /// It is responsible for starting and stopping all other components.
 /// It is also responsible for handling events and drawing the UI.
 impl Root {
-    pub fn new(event_sender: Sender<Event>, logs: LogCollector) -> Self {
+    pub fn new(
+        event_sender: Sender<Event>,
+        logs: LogCollector,
+        shutdown: CancellationToken,
+        settings: Settings,
+    ) -> Self {
         let authentication_data = Arc::new(RwLock::new(None));
-        let authentication =
-            Authentication::new(event_sender.clone(), Arc::clone(&authentication_data));
-        let home = Home::new(event_sender.clone(), Arc::clone(&authentication_data));
-        // show logs if we set TOOT_RS_SHOW_LOGS to anything
-        let show_logs = std::env::var("TOOT_RS_SHOW_LOGS").is_ok();
+        let authentication = Authentication::new(
+            event_sender.clone(),
+            Arc::clone(&authentication_data),
+            shutdown,
+            settings.auth.clone(),
+        );
+        let home = Home::new(
+            event_sender.clone(),
+            Arc::clone(&authentication_data),
+            &settings,
+        );
+        let history = History::new(
+            event_sender.clone(),
+            Arc::clone(&authentication_data),
+            &settings.ui,
+        );
+        let composer = Composer::new(event_sender.clone(), Arc::clone(&authentication_data));
+        let accounts = Accounts::new(event_sender.clone());
+        let keys = Keys::new(Keymap::new(&settings.keybindings).unwrap_or_else(|err| {
+            error!("Using the default keys: {err:#}");
+            Keymap::default()
+        }));
+        theme::set(Theme::new(&settings.theme).unwrap_or_else(|err| {
+            error!("Using the default theme: {err:#}");
+            Theme::default()
+        }));
+        let mut command_line = CommandLine::new();
+        command_line.set_themes(theme::names(&settings.theme));
         Self {
-            _event_sender: event_sender,
+            event_sender,
             state: State::Authentication,
+            authentication_data,
             authentication,
             home,
+            history,
+            composer,
+            accounts,
+            resume: None,
             logs,
-            show_logs,
+            show_logs: settings.logging.show,
+            settings,
+            keys,
+            help: None,
+            command_line,
+            toast: None,
         }
     }
 
-    pub fn start(&mut self) {
+    /// Starts by logging in to the named account, or else to the current account.
+    pub fn start(&mut self, account: Option<String>) {
         info!("Starting root component");
-        self.authentication.start();
+        self.authentication
+            .start(account.map_or(Login::Current, Login::Account));
+    }
+
+    /// Logs out of the named account. Logging out of the current account returns to the
+    /// authentication screen, which logs in to another saved account if there is one.
+    async fn log_out(&mut self, name: &str) {
+        let revoke_error = match authentication::log_out(
+            name,
+            self.authentication.passphrase().as_deref(),
+        )
+        .await
+        {
+            Ok(revoke_error) => revoke_error,
+            Err(err) => {
+                error!("Unable to log out of {name}: {err:#}");
+                self.accounts
+                    .show_message(format!("Unable to log out of {name}: {err}"));
+                return;
+            }
+        };
+        if let Some(err) = revoke_error {
+            self.toast = Some(Toast::new(format!(
+                "Logged out of {name}, but its access token was not revoked: {err}"
+            )));
+        }
+        if self.logged_in_account().as_deref() == Some(name) {
+            *self.authentication_data.write().expect("lock poisoned") = None;
+            self.resume = None;
+            self.authentication.start(Login::Current);
+            self.state = State::Authentication;
+        } else {
+            self.accounts.start(self.logged_in_account());
+            self.accounts.show_message(format!("Logged out of {name}"));
+        }
+    }
+
+    /// Shows the authentication screen to sign in again. If the user signs in to the same account,
+    /// the app returns to the current view with its state intact.
+    fn sign_in_again(&mut self, login: Login) {
+        if !matches!(self.state, State::Authentication) {
+            self.resume = self.logged_in_account().map(|name| (self.state, name));
+        }
+        self.help = None;
+        self.authentication.start(login);
+        self.state = State::Authentication;
+    }
+
+    /// Asks the user to sign in again if `err` shows that the access token was rejected.
+    fn check_session(&self, err: &color_eyre::Report) {
+        if !authentication::is_unauthorized(err) {
+            return;
+        }
+        if let Some(name) = self.logged_in_account() {
+            self.event_sender.try_send(Event::SessionExpired(name)).ok();
+        }
+    }
+
+    /// Applies settings that changed while the app is running, without restarting any views.
+    fn apply_settings(&mut self, settings: Settings) {
+        if settings.logging.level != self.settings.logging.level {
+            if let Err(err) = logging::set_filter(&settings.logging) {
+                error!("Unable to change the log level: {err:#}");
+            }
+        }
+        self.show_logs = settings.logging.show;
+        self.authentication.apply_settings(settings.auth.clone());
+        self.home.apply_settings(&settings);
+        self.history.apply_settings(&settings.ui);
+        match Keymap::new(&settings.keybindings) {
+            Ok(keymap) => self.keys = Keys::new(keymap),
+            Err(err) => error!("Unable to change the keys: {err:#}"),
+        }
+        match Theme::new(&settings.theme) {
+            Ok(new_theme) => theme::set(new_theme),
+            Err(err) => error!("Unable to change the theme: {err:#}"),
+        }
+        self.command_line.set_themes(theme::names(&settings.theme));
+        self.settings = settings;
+        info!("Applied the new settings");
+        self.toast = Some(Toast::new("Settings reloaded"));
+    }
+
+    /// The bindings that apply to key presses, or `None` while the view is asking a yes or no
+    /// question and takes every key.
+    fn key_context(&self) -> Option<Context> {
+        if self.help.is_some() {
+            return Some(Context::Help);
+        }
+        if self.command_line.is_open() {
+            return Some(Context::CommandLine);
+        }
+        self.view_context()
+    }
+
+    /// The bindings of the current view, or `None` while it is asking a yes or no question.
+    fn view_context(&self) -> Option<Context> {
+        match self.state {
+            State::Authentication => Some(Context::Authentication),
+            State::Home if self.home.is_confirming() => None,
+            State::Home => Some(Context::Home),
+            State::History => Some(Context::History),
+            State::Composer if self.composer.is_confirming() => None,
+            State::Composer => Some(Context::Composer),
+            State::Accounts if self.accounts.is_confirming() => None,
+            State::Accounts => Some(Context::Accounts),
+        }
+    }
+
+    /// Runs an action from the keymap in the current view.
+    fn handle_action(&mut self, action: Action, count: usize) -> Outcome {
+        if let Some(help) = &mut self.help {
+            match action {
+                Action::Back => self.help = None,
+                Action::Down => help.scroll_down(count),
+                Action::Up => help.scroll_up(count),
+                _ => return Outcome::Ignored,
+            }
+            return Outcome::Handled;
+        }
+        match (self.state, action) {
+            (_, Action::Help) => {
+                if let Some(context) = self.view_context() {
+                    self.help = Some(Help::new(self.keys.keymap(), context));
+                }
+                Outcome::Handled
+            }
+            (_, Action::CommandLine) => {
+                self.command_line.open(Mode::Line);
+                Outcome::Handled
+            }
+            (_, Action::Palette) => {
+                self.command_line.open(Mode::Palette);
+                Outcome::Handled
+            }
+            (_, Action::Quit) => {
+                if let Err(err) = self.event_sender.try_send(Event::Quit) {
+                    error!("Unable to quit: {err}");
+                }
+                Outcome::Handled
+            }
+            // backing out of switching or adding an account keeps the current account
+            (State::Authentication, Action::Back) if self.logged_in_account().is_some() => {
+                self.authentication.cancel();
+                self.state = self.resume.take().map_or(State::Home, |(state, _)| state);
+                Outcome::Handled
+            }
+            (State::Composer, Action::Back) if self.composer.confirm_leave() => Outcome::Handled,
+            (State::History | State::Composer | State::Accounts, Action::Back) => {
+                self.state = State::Home;
+                Outcome::Handled
+            }
+            (State::Home, _) => self.home.handle_action(action, count),
+            (State::History, _) => self.history.handle_action(action, count),
+            (State::Composer, _) => self.composer.handle_action(action),
+            (State::Accounts, _) => self.accounts.handle_action(action, count),
+            (State::Authentication, _) => Outcome::Ignored,
+        }
+    }
+
+    fn handle_command_line_action(&mut self, action: Action, count: usize) {
+        match action {
+            Action::Back => self.command_line.close(),
+            Action::Execute => {
+                if let Some(line) = self.command_line.submit() {
+                    self.run_command(&line);
+                }
+            }
+            Action::Complete => self.command_line.complete(),
+            Action::Up => self.command_line.up(count),
+            Action::Down => self.command_line.down(count),
+            _ => {}
+        }
+    }
+
+    /// Runs a line from the command line, showing what went wrong in a toast.
+    fn run_command(&mut self, line: &str) {
+        if let Err(err) = self.try_run_command(line) {
+            error!("Command {line:?} failed: {err:#}");
+            self.check_session(&err);
+            self.toast = Some(Toast::new(format!("{err:#}")));
+        }
+    }
+
+    fn try_run_command(&mut self, line: &str) -> Result<()> {
+        match line.parse::<Command>()? {
+            Command::Action(action) => {
+                if self.handle_action(action, 1) == Outcome::Ignored {
+                    bail!(":{} is not available here", action.name());
+                }
+            }
+            Command::Timeline(timeline) => {
+                self.home.open(timeline);
+                self.state = State::Home;
+            }
+            Command::Open(url) => {
+                webbrowser::open(&url).wrap_err_with(|| format!("unable to open {url}"))?;
+            }
+            Command::Account(name) => {
+                let name = find_account(&name)?;
+                self.authentication.start(Login::Account(name));
+                self.state = State::Authentication;
+            }
+            Command::LogLevel(level) => {
+                let mut logging = self.settings.logging.clone();
+                logging.level = level;
+                logging::set_filter(&logging)?;
+                self.toast = Some(Toast::new(format!("Log level set to {}", logging.level)));
+                self.settings.logging = logging;
+            }
+            Command::Theme(name) => {
+                theme::set(Theme::named(&self.settings.theme, &name)?);
+                self.toast = Some(Toast::new(format!("Theme set to {name}")));
+            }
+        }
+        Ok(())
+    }
+
+    fn logged_in_account(&self) -> Option<String> {
+        self.authentication_data
+            .read()
+            .expect("lock poisoned")
+            .as_ref()
+            .map(|auth| auth.name.clone())
+    }
+
+    /// Passes the result of a background action to the timeline whatever view is open, so that the
+    /// timeline stays up to date and shows the failures in its status bar.
+    fn handle_action_result(&mut self, event: &Event) -> Outcome {
+        match (self.state, event) {
+            // the composer sent the status, or shows why it failed and keeps the draft
+            (State::Composer, _) if self.composer.handle_event(event) == Outcome::Handled => {
+                if let Event::ActionFailed(_) = event {
+                    return Outcome::Handled;
+                }
+                self.state = State::Home;
+            }
+            // the history failed to load, so go back to the timeline that shows the error
+            (State::History, Event::ActionFailed(_)) if self.history.is_loading() => {
+                self.state = State::Home;
+            }
+            _ => {}
+        }
+        self.home.handle_event(event)
     }
 
     /// Handles an event.
     /// Returns an `Outcome` that indicates whether the event was handled or not.
     pub async fn handle_event(&mut self, event: &Event) -> Outcome {
-        match self.state {
-            State::Authentication => {
-                if event == &Event::AuthenticationSuccess {
-                    self.state = State::Home;
-                    self.home.start().await.ok();
+        match event {
+            Event::Tick if self.toast.as_ref().is_some_and(Toast::is_expired) => {
+                self.toast = None;
+            }
+            Event::SettingsChanged(settings) => {
+                self.apply_settings(*settings.clone());
+                return Outcome::Handled;
+            }
+            Event::SettingsInvalid(message) => {
+                self.toast = Some(Toast::new(format!("Settings not reloaded: {message}")));
+                return Outcome::Handled;
+            }
+            Event::ScopeRequired(scope) => {
+                self.sign_in_again(Login::Reauthorize(scope.clone()));
+                return Outcome::Handled;
+            }
+            // background actions that fail at the same time report the expiry more than once
+            Event::SessionExpired(_)
+                if matches!(self.state, State::Authentication) && self.resume.is_some() =>
+            {
+                return Outcome::Handled;
+            }
+            Event::SessionExpired(name) => {
+                self.sign_in_again(Login::Renew(name.clone()));
+                return Outcome::Handled;
+            }
+            Event::StatusPosted(_)
+            | Event::StatusEdited(_)
+            | Event::StatusDeleted(_)
+            | Event::TimelineLoaded(..)
+            | Event::ActionFailed(_) => return self.handle_action_result(event),
+            Event::Crossterm(CrosstermEvent::Key(key)) => {
+                if let Some(context) = self.key_context() {
+                    match self.keys.resolve(context, *key) {
+                        Resolution::Action(action, count) if self.command_line.is_open() => {
+                            self.handle_command_line_action(action, count);
+                            return Outcome::Handled;
+                        }
+                        Resolution::Action(action, count) => {
+                            if self.handle_action(action, count) == Outcome::Handled {
+                                return Outcome::Handled;
+                            }
+                        }
+                        Resolution::Pending => return Outcome::Handled,
+                        Resolution::Unbound => {}
+                    }
+                }
+                // the help overlay takes every other key for its search
+                if let Some(help) = &mut self.help {
+                    help.handle_event(&CrosstermEvent::Key(*key));
+                    return Outcome::Handled;
+                }
+                if self.command_line.is_open() {
+                    self.command_line.handle_event(&CrosstermEvent::Key(*key));
                     return Outcome::Handled;
                 }
-                self.authentication.handle_event(event).await
             }
-            State::Home => self.home.handle_event(event),
+            _ => {}
+        }
+        match self.state {
+            State::Authentication => match event {
+                Event::AuthenticationSuccess => {
+                    let account = self.logged_in_account();
+                    if let Some((state, _)) = self
+                        .resume
+                        .take()
+                        .filter(|(_, name)| account.as_ref() == Some(name))
+                    {
+                        self.state = state;
+                        return Outcome::Handled;
+                    }
+                    // start from a fresh timeline, as the account may have changed
+                    self.home = Home::new(
+                        self.event_sender.clone(),
+                        Arc::clone(&self.authentication_data),
+                        &self.settings,
+                    );
+                    self.state = State::Home;
+                    if let Err(err) = self.home.start().await {
+                        self.check_session(&err);
+                    }
+                    Outcome::Handled
+                }
+                _ => self.authentication.handle_event(event).await,
+            },
+            State::Home => match event {
+                Event::ShowHistory(status_id) => {
+                    self.history.start(status_id);
+                    self.state = State::History;
+                    Outcome::Handled
+                }
+                Event::Compose(draft) => {
+                    self.composer.start(*draft.clone());
+                    self.state = State::Composer;
+                    Outcome::Handled
+                }
+                Event::ShowAccounts => {
+                    self.accounts.start(self.logged_in_account());
+                    self.state = State::Accounts;
+                    Outcome::Handled
+                }
+                _ => self.home.handle_event(event),
+            },
+            State::History => match event {
+                Event::HistoryLoaded(status_id, revisions) => {
+                    self.history.load(status_id, revisions.clone());
+                    Outcome::Handled
+                }
+                _ => Outcome::Ignored,
+            },
+            State::Composer => match event {
+                Event::DraftDiscarded => {
+                    self.state = State::Home;
+                    Outcome::Handled
+                }
+                _ => self.composer.handle_event(event),
+            },
+            State::Accounts => match event {
+                Event::SwitchAccount(name) => {
+                    self.authentication.start(Login::Account(name.clone()));
+                    self.state = State::Authentication;
+                    Outcome::Handled
+                }
+                Event::AddAccount => {
+                    self.authentication.start(Login::New);
+                    self.state = State::Authentication;
+                    Outcome::Handled
+                }
+                Event::Logout(name) => {
+                    self.log_out(name).await;
+                    Outcome::Handled
+                }
+                _ => self.accounts.handle_event(event),
+            },
+        }
+    }
+}
+
+/// The saved account with this name, or the only one whose name starts with it, so that
+/// `:account personal` finds `personal@mastodon.social`.
+fn find_account(name: &str) -> Result<String> {
+    let config = Config::load()?;
+    if config.accounts.contains_key(name) {
+        return Ok(name.to_string());
+    }
+    let matches: Vec<&String> = config
+        .accounts
+        .keys()
+        .filter(|account| account.starts_with(name))
+        .collect();
+    match matches.as_slice() {
+        [] => bail!("no account named {name}"),
+        [account] => Ok((*account).clone()),
+        accounts => {
+            let accounts: Vec<&str> = accounts.iter().map(|account| account.as_str()).collect();
+            bail!("{name} matches several accounts: {}", accounts.join(", "))
         }
     }
 }
@@ -80,20 +542,54 @@ impl Widget for &Root {
             Length(StatusBar::HEIGHT),
         ])
         .areas(area);
-        match self.state {
+        let status = match self.state {
             State::Authentication => {
                 TitleBar::new(&self.authentication.title()).render(top, buf);
-                StatusBar::new("Loading...").render(bottom, buf);
                 self.authentication.render(mid, buf);
+                "Loading..."
             }
             State::Home => {
                 TitleBar::new(self.home.title()).render(top, buf);
-                StatusBar::new(self.home.status()).render(bottom, buf);
                 self.home.render(mid, buf);
+                self.home.status()
             }
-        }
+            State::History => {
+                TitleBar::new(History::title()).render(top, buf);
+                self.history.render(mid, buf);
+                self.history.status()
+            }
+            State::Composer => {
+                TitleBar::new(self.composer.title()).render(top, buf);
+                self.composer.render(mid, buf);
+                ""
+            }
+            State::Accounts => {
+                TitleBar::new(Accounts::title()).render(top, buf);
+                self.accounts.render(mid, buf);
+                self.accounts.status()
+            }
+        };
+        let hints = self
+            .key_context()
+            .map(|context| self.keys.keymap().hints(context))
+            .unwrap_or_default();
+        // show a count or the start of a sequence until the binding is complete
+        let pending = self.keys.pending_text();
+        let status = if self.keys.is_pending() {
+            &pending
+        } else {
+            status
+        };
+        StatusBar::new(hints, status).render(bottom, buf);
         if self.show_logs {
             self.logs.render(logs, buf);
         };
+        if let Some(help) = &self.help {
+            help.render(mid, buf);
+        }
+        self.command_line.render(mid, buf);
+        if let Some(toast) = &self.toast {
+            toast.render(mid, buf);
+        }
     }
 }
@@ -0,0 +1,412 @@
+//! User preferences, loaded from `settings.toml` in the XDG config directory.
+//!
+//! The preferences are kept apart from the credentials in `config.toml`, so that the settings file
+//! can be checked into a dotfiles repository. Every key is optional and unknown keys are an error,
+//! so that typos don't go unnoticed.
+//!
+//! The TUI watches the file and applies changes while it runs, keeping the last good settings if the
+//! file becomes invalid.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use chrono::format::{Item, StrftimeItems};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use mastodon_async::scopes::Scopes;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, metadata::LevelFilter, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    config,
+    event::Event,
+    keymap::{Action, Context, Keymap},
+    theme::{self, ColorDepth, Slot},
+};
+
+/// The most statuses that Mastodon returns in a single page of a timeline
+const MAX_TIMELINE_LIMIT: u32 = 40;
+
+/// How often to check whether the settings file has changed
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The preferences in `settings.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub ui: Ui,
+    pub keybindings: Keybindings,
+    pub theme: Theme,
+    pub timelines: Timelines,
+    pub notifications: Notifications,
+    pub logging: Logging,
+    pub auth: Auth,
+}
+
+/// `[ui]`: how statuses are displayed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ui {
+    /// The strftime format for absolute timestamps, shown in the local timezone
+    pub time_format: String,
+    /// Strip custom emoji shortcodes, which screen readers read out letter by letter
+    pub screen_reader: bool,
+}
+
+/// `[keybindings]`: the keys for actions, with a table per view and a `global` table for every
+/// view that isn't a text input. Each action maps to a key or a list of keys, which replace its
+/// default keys, e.g. `down = ["j", "ctrl-n"]`. Keys are written like `G`, `esc` or `ctrl-s`, and
+/// `"g g"` is a sequence of keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keybindings {
+    pub global: BTreeMap<Action, KeyList>,
+    pub authentication: BTreeMap<Action, KeyList>,
+    pub home: BTreeMap<Action, KeyList>,
+    pub history: BTreeMap<Action, KeyList>,
+    pub composer: BTreeMap<Action, KeyList>,
+    pub accounts: BTreeMap<Action, KeyList>,
+    pub help: BTreeMap<Action, KeyList>,
+    pub command_line: BTreeMap<Action, KeyList>,
+}
+
+/// The keys of an action in `[keybindings]`: one key, or a list of keys.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `[theme]`: the colors of the app, from a preset or one of the user's themes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// `auto`, `dark`, `light`, `high_contrast` or the name of a theme in `themes`. `auto` is dark
+    /// or light to suit the terminal background, where the terminal tells.
+    pub name: String,
+    /// How many colors the terminal shows: `auto`, `truecolor`, `256` or `16`
+    pub color_depth: ColorDepth,
+    /// The user's themes, by name
+    pub themes: BTreeMap<String, UserTheme>,
+}
+
+/// A theme in `[theme.themes]`: a preset with some of its colors changed, e.g.
+/// `colors = { accent = "#b58900", highlight = "236" }`. Colors are names like `light-red`, hex
+/// RGB values or indices of the 256 colors.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserTheme {
+    /// The preset that the theme changes
+    pub base: String,
+    pub colors: BTreeMap<Slot, String>,
+}
+
+/// `[timelines]`: how timelines are loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timelines {
+    /// How many statuses to load at a time, at most 40
+    pub limit: u32,
+}
+
+/// `[notifications]`: how the app notifies the user. No notifications are configurable yet.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {}
+
+/// `[logging]`: what is logged, and whether the logs are shown in the app.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Logging {
+    /// Show the logs below the main view
+    pub show: bool,
+    /// The log filter, e.g. `debug` or `tooters=debug,info`. `RUST_LOG` takes precedence, and
+    /// `--log-level` over both.
+    pub level: String,
+}
+
+/// `[auth]`: how tooters signs in to servers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// The space separated scopes to ask for. The default leaves out the admin and push scopes that
+    /// tooters does not use.
+    pub scopes: String,
+    /// The port for the local server that receives the authorization callback. A random free port
+    /// is used if unset.
+    pub callback_port: Option<u16>,
+    /// Encrypt new access tokens with a passphrase, which the user enters at startup
+    pub encrypt_tokens: bool,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            time_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            screen_reader: false,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "auto".to_string(),
+            color_depth: ColorDepth::Auto,
+            themes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for UserTheme {
+    fn default() -> Self {
+        Self {
+            base: "auto".to_string(),
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Timelines {
+    fn default() -> Self {
+        Self { limit: 20 }
+    }
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            show: false,
+            level: "info".to_string(),
+        }
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            scopes: "read write follow".to_string(),
+            callback_port: None,
+            encrypt_tokens: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings file from the XDG config directory
+    /// e.g. ~/.config/tooters/settings.toml
+    /// Returns the default settings if the file does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the offending key if the file is not valid.
+    pub fn load() -> Result<Self> {
+        let settings_file = config::config_file("settings.toml")?;
+        if !settings_file.exists() {
+            info!("No settings file at {}", settings_file.display());
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&settings_file).with_context(|| {
+            format!(
+                "unable to read settings file from {}",
+                settings_file.display()
+            )
+        })?;
+        let settings = Self::parse(&contents)
+            .wrap_err_with(|| format!("invalid settings file {}", settings_file.display()))?;
+        info!("Loaded settings file from {}", settings_file.display());
+        Ok(settings)
+    }
+
+    /// Parses and validates the contents of a settings file.
+    fn parse(contents: &str) -> Result<Self> {
+        let settings: Self = toml::from_str(contents)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Checks the values that TOML types alone can't, naming the key of any invalid value.
+    fn validate(&self) -> Result<()> {
+        self.ui
+            .time_format_items()
+            .wrap_err("invalid value for ui.time_format")?;
+        if !(1..=MAX_TIMELINE_LIMIT).contains(&self.timelines.limit) {
+            bail!(
+                "invalid value for timelines.limit: {} is not between 1 and {MAX_TIMELINE_LIMIT}",
+                self.timelines.limit
+            );
+        }
+        Keymap::new(&self.keybindings)?;
+        if let Some(name) = self
+            .theme
+            .themes
+            .keys()
+            .find(|name| theme::PRESETS.contains(&name.as_str()))
+        {
+            bail!("invalid key theme.themes.{name}: {name} is a preset theme");
+        }
+        for name in self.theme.themes.keys() {
+            theme::Theme::named(&self.theme, name)?;
+        }
+        theme::Theme::new(&self.theme).wrap_err("invalid value for theme.name")?;
+        self.logging
+            .filter()
+            .wrap_err("invalid value for logging.level")?;
+        self.auth
+            .scopes()
+            .wrap_err("invalid value for auth.scopes")?;
+        Ok(())
+    }
+}
+
+/// Watches the settings file while the app runs. When it changes, sends
+/// [`Event::SettingsChanged`] with the new settings, after `apply` has applied the command line
+/// flags to them, or [`Event::SettingsInvalid`] if the new file is not valid.
+pub async fn watch<F>(event_sender: Sender<Event>, mut current: Settings, apply: F)
+where
+    F: Fn(&mut Settings) -> Result<()>,
+{
+    let settings_file = match config::config_file("settings.toml") {
+        Ok(settings_file) => settings_file,
+        Err(err) => {
+            warn!("Not watching the settings file: {err:#}");
+            return;
+        }
+    };
+    let mut modified = modified_time(&settings_file);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let latest = modified_time(&settings_file);
+        if latest == modified {
+            continue;
+        }
+        modified = latest;
+        let settings = Settings::load().and_then(|mut settings| {
+            apply(&mut settings)?;
+            Ok(settings)
+        });
+        let event = match settings {
+            Ok(settings) if settings == current => continue,
+            Ok(settings) => {
+                current = settings.clone();
+                Event::SettingsChanged(Box::new(settings))
+            }
+            Err(err) => {
+                warn!("Keeping the last good settings: {err:#}");
+                Event::SettingsInvalid(format!("{err:#}"))
+            }
+        };
+        if event_sender.send(event).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// When the file was last modified, or `None` if it does not exist.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Ui {
+    /// The parsed [`Ui::time_format`].
+    pub fn time_format_items(&self) -> Result<Vec<Item<'static>>> {
+        StrftimeItems::new(&self.time_format)
+            .parse_to_owned()
+            .map_err(|err| eyre!("{:?} is not a strftime format: {err}", self.time_format))
+    }
+}
+
+impl Keybindings {
+    /// The table of each view.
+    pub fn tables(&self) -> [(Context, &BTreeMap<Action, KeyList>); 8] {
+        [
+            (Context::Global, &self.global),
+            (Context::Authentication, &self.authentication),
+            (Context::Home, &self.home),
+            (Context::History, &self.history),
+            (Context::Composer, &self.composer),
+            (Context::Accounts, &self.accounts),
+            (Context::Help, &self.help),
+            (Context::CommandLine, &self.command_line),
+        ]
+    }
+}
+
+impl KeyList {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Self::One(key) => std::slice::from_ref(key),
+            Self::Many(keys) => keys,
+        }
+    }
+}
+
+impl Logging {
+    /// The parsed [`Logging::level`].
+    pub fn filter(&self) -> Result<EnvFilter> {
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .parse(&self.level)
+            .map_err(|err| eyre!("{:?} is not a log filter: {err}", self.level))
+    }
+}
+
+impl Auth {
+    /// The parsed [`Auth::scopes`].
+    pub fn scopes(&self) -> Result<Scopes> {
+        Scopes::from_str(&self.scopes).map_err(|err| eyre!("{:?}: {err}", self.scopes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The error message of settings that fail to parse, with its causes.
+    fn error(contents: &str) -> String {
+        format!("{:#}", Settings::parse(contents).unwrap_err())
+    }
+
+    #[test]
+    fn parses_an_empty_file_as_the_defaults() {
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+        let settings = Settings::parse("[timelines]\nlimit = 40\n\n[notifications]\n").unwrap();
+        assert_eq!(settings.timelines.limit, 40);
+        assert_eq!(settings.ui, Ui::default());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(error("[ui]\ntime_fromat = \"%H:%M\"").contains("time_fromat"));
+        assert!(error("[colours]\n").contains("colours"));
+        assert!(error("[keybindings.home]\nfly = \"f\"").contains("fly"));
+    }
+
+    #[test]
+    fn names_the_key_of_invalid_values() {
+        let err = error("[timelines]\nlimit = 0");
+        assert!(err.contains("timelines.limit"), "{err}");
+        let err = error("[timelines]\nlimit = 41");
+        assert!(err.contains("timelines.limit"), "{err}");
+        let err = error("[ui]\ntime_format = \"%Q\"");
+        assert!(err.contains("ui.time_format"), "{err}");
+        let err = error("[logging]\nlevel = \"tooters=loud\"");
+        assert!(err.contains("logging.level"), "{err}");
+        let err = error("[auth]\nscopes = \"read shout\"");
+        assert!(err.contains("auth.scopes"), "{err}");
+        let err = error("[timelines]\nlimit = \"many\"");
+        assert!(err.contains("limit"), "{err}");
+    }
+}
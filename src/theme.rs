@@ -0,0 +1,519 @@
+//! The colors of the app.
+//!
+//! Views don't name colors, they ask the current [`Theme`] for the color of what they draw, e.g.
+//! [`Theme::accent`] for account names. The `[theme]` settings choose a preset or one of the user's
+//! themes, which change some colors of a preset, and the colors are reduced to what the terminal
+//! can show.
+
+use std::sync::RwLock;
+
+use color_eyre::{eyre::eyre, Result};
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+use crate::settings;
+
+/// The names of the built-in themes. `auto` is dark or light, whichever suits the terminal.
+pub const PRESETS: &[&str] = &["auto", "dark", "light", "high_contrast"];
+
+/// The theme that the app is drawn with. It is global, like the log filter, as much of the drawing
+/// happens in free functions that don't have the settings at hand.
+static CURRENT: RwLock<Theme> = RwLock::new(Theme::DARK);
+
+/// The theme that the app is drawn with.
+pub fn current() -> Theme {
+    *CURRENT.read().expect("lock poisoned")
+}
+
+/// Changes the theme that the app is drawn with, from the next frame on.
+pub fn set(theme: Theme) {
+    *CURRENT.write().expect("lock poisoned") = theme;
+}
+
+/// A color in a theme, named for what it is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    /// The background of the title and status bars, and of messages
+    Bar,
+    /// The text of the bars
+    BarText,
+    /// Less important text in the bars, e.g. the title of the view
+    BarMuted,
+    /// The background of the selected item of a list
+    Highlight,
+    /// The text of the selected item, if it should change
+    HighlightText,
+    /// Hints and other less important text
+    Muted,
+    /// Account handles, headings and the focused field
+    Accent,
+    /// Display names
+    Name,
+    /// Boosts and links
+    Link,
+    /// Replies
+    Reply,
+    /// Custom emoji shortcodes
+    Emoji,
+    /// Messages that something worked, or what to do next
+    Success,
+    /// Notices and warnings
+    Warning,
+    /// Errors
+    Error,
+    /// Words added by an edit
+    Added,
+    /// Words removed by an edit
+    Removed,
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum ColorDepth {
+    /// Guess from `COLORTERM` and `TERM`
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    /// Any RGB color
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    /// The 256 colors of xterm
+    #[serde(rename = "256")]
+    Colors256,
+    /// The 16 ANSI colors, whose exact colors depend on the terminal's palette
+    #[serde(rename = "16")]
+    Colors16,
+}
+
+/// The colors of the app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub bar: Color,
+    pub bar_text: Color,
+    pub bar_muted: Color,
+    pub highlight: Color,
+    pub highlight_text: Option<Color>,
+    pub muted: Color,
+    pub accent: Color,
+    pub name: Color,
+    pub link: Color,
+    pub reply: Color,
+    pub emoji: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    pub added: Color,
+    pub removed: Color,
+    /// Shows the selected item reversed instead of with the highlight colors, for terminals that
+    /// have no background color subtle enough to keep the text readable
+    reverse_highlight: bool,
+}
+
+impl Theme {
+    /// Light text on a dark terminal, the colors that tooters has always had.
+    pub const DARK: Self = Self {
+        bar: Color::Blue,
+        bar_text: Color::White,
+        bar_muted: Color::Gray,
+        highlight: Color::Rgb(16, 32, 64),
+        highlight_text: None,
+        muted: Color::Gray,
+        accent: Color::Yellow,
+        name: Color::Green,
+        link: Color::Cyan,
+        reply: Color::Magenta,
+        emoji: Color::Magenta,
+        success: Color::Green,
+        warning: Color::Yellow,
+        error: Color::Red,
+        added: Color::Green,
+        removed: Color::Red,
+        reverse_highlight: false,
+    };
+
+    /// Dark text on a light terminal, where the ANSI yellows and cyans are hard to read.
+    pub const LIGHT: Self = Self {
+        bar: Color::Rgb(40, 80, 160),
+        bar_text: Color::Rgb(255, 255, 255),
+        bar_muted: Color::Rgb(210, 220, 240),
+        highlight: Color::Rgb(215, 228, 250),
+        highlight_text: None,
+        muted: Color::Rgb(100, 100, 100),
+        accent: Color::Rgb(150, 90, 0),
+        name: Color::Rgb(0, 120, 40),
+        link: Color::Rgb(0, 100, 150),
+        reply: Color::Rgb(140, 0, 140),
+        emoji: Color::Rgb(140, 0, 140),
+        success: Color::Rgb(0, 120, 40),
+        warning: Color::Rgb(150, 90, 0),
+        error: Color::Rgb(190, 0, 0),
+        added: Color::Rgb(0, 120, 40),
+        removed: Color::Rgb(190, 0, 0),
+        reverse_highlight: false,
+    };
+
+    /// Bright ANSI colors only, with black on white for the bars and the selected item.
+    pub const HIGH_CONTRAST: Self = Self {
+        bar: Color::White,
+        bar_text: Color::Black,
+        bar_muted: Color::Black,
+        highlight: Color::White,
+        highlight_text: Some(Color::Black),
+        muted: Color::White,
+        accent: Color::LightYellow,
+        name: Color::LightGreen,
+        link: Color::LightCyan,
+        reply: Color::LightMagenta,
+        emoji: Color::LightMagenta,
+        success: Color::LightGreen,
+        warning: Color::LightYellow,
+        error: Color::LightRed,
+        added: Color::LightGreen,
+        removed: Color::LightRed,
+        reverse_highlight: false,
+    };
+
+    /// The theme chosen by the `[theme]` settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key of an unknown theme or an invalid color.
+    pub fn new(settings: &settings::Theme) -> Result<Self> {
+        Self::named(settings, &settings.name)
+    }
+
+    /// A preset, or one of the themes in the `[theme]` settings, reduced to the colors that the
+    /// terminal can show.
+    pub fn named(settings: &settings::Theme, name: &str) -> Result<Self> {
+        let theme = if let Some(preset) = Self::preset(name) {
+            preset
+        } else {
+            let user = settings.themes.get(name).ok_or_else(|| {
+                eyre!(
+                    "unknown theme {name:?}, expected one of {}",
+                    names(settings).join(", ")
+                )
+            })?;
+            let mut theme = Self::preset(&user.base).ok_or_else(|| {
+                eyre!(
+                    "theme.themes.{name}.base: {:?} is not one of {}",
+                    user.base,
+                    PRESETS.join(", ")
+                )
+            })?;
+            for (slot, color) in &user.colors {
+                let color = color.parse().map_err(|_| {
+                    eyre!(
+                        "theme.themes.{name}.colors.{}: {color:?} is not a color",
+                        slot.name()
+                    )
+                })?;
+                theme.set(*slot, color);
+            }
+            theme
+        };
+        Ok(theme.reduce(settings.color_depth.detect()))
+    }
+
+    fn preset(name: &str) -> Option<Self> {
+        match name {
+            "auto" if is_light_background() => Some(Self::LIGHT),
+            "auto" | "dark" => Some(Self::DARK),
+            "light" => Some(Self::LIGHT),
+            "high_contrast" => Some(Self::HIGH_CONTRAST),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, slot: Slot, color: Color) {
+        match slot {
+            Slot::Bar => self.bar = color,
+            Slot::BarText => self.bar_text = color,
+            Slot::BarMuted => self.bar_muted = color,
+            Slot::Highlight => self.highlight = color,
+            Slot::HighlightText => self.highlight_text = Some(color),
+            Slot::Muted => self.muted = color,
+            Slot::Accent => self.accent = color,
+            Slot::Name => self.name = color,
+            Slot::Link => self.link = color,
+            Slot::Reply => self.reply = color,
+            Slot::Emoji => self.emoji = color,
+            Slot::Success => self.success = color,
+            Slot::Warning => self.warning = color,
+            Slot::Error => self.error = color,
+            Slot::Added => self.added = color,
+            Slot::Removed => self.removed = color,
+        }
+    }
+
+    /// Replaces the colors that the terminal can't show with the closest ones that it can.
+    fn reduce(self, depth: ColorDepth) -> Self {
+        let reduce = |color| reduce(color, depth);
+        Self {
+            bar: reduce(self.bar),
+            bar_text: reduce(self.bar_text),
+            bar_muted: reduce(self.bar_muted),
+            highlight: reduce(self.highlight),
+            highlight_text: self.highlight_text.map(reduce),
+            muted: reduce(self.muted),
+            accent: reduce(self.accent),
+            name: reduce(self.name),
+            link: reduce(self.link),
+            reply: reduce(self.reply),
+            emoji: reduce(self.emoji),
+            success: reduce(self.success),
+            warning: reduce(self.warning),
+            error: reduce(self.error),
+            added: reduce(self.added),
+            removed: reduce(self.removed),
+            // the closest ANSI color to a subtle background is usually the background itself
+            reverse_highlight: depth == ColorDepth::Colors16
+                && matches!(self.highlight, Color::Rgb(..) | Color::Indexed(16..)),
+        }
+    }
+
+    /// The style of the title and status bars.
+    pub fn bar(&self) -> Style {
+        Style::default().fg(self.bar_text).bg(self.bar)
+    }
+
+    /// The style of the selected item of a list.
+    pub fn highlight(&self) -> Style {
+        if self.reverse_highlight {
+            return Style::default().add_modifier(Modifier::REVERSED);
+        }
+        let style = Style::default().bg(self.highlight);
+        self.highlight_text
+            .map_or(style, |highlight_text| style.fg(highlight_text))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+impl Slot {
+    /// The name of the color in the settings file.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Bar => "bar",
+            Self::BarText => "bar_text",
+            Self::BarMuted => "bar_muted",
+            Self::Highlight => "highlight",
+            Self::HighlightText => "highlight_text",
+            Self::Muted => "muted",
+            Self::Accent => "accent",
+            Self::Name => "name",
+            Self::Link => "link",
+            Self::Reply => "reply",
+            Self::Emoji => "emoji",
+            Self::Success => "success",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Added => "added",
+            Self::Removed => "removed",
+        }
+    }
+}
+
+impl ColorDepth {
+    /// The color depth, guessing it from the environment if it is [`ColorDepth::Auto`]. Terminals
+    /// that support RGB colors set `COLORTERM`, but as it is often lost over ssh, `TERM` is the
+    /// fallback.
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if matches!(colorterm.as_str(), "truecolor" | "24bit") || cfg!(windows) {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Colors256
+        } else {
+            Self::Colors16
+        }
+    }
+}
+
+/// The names of the presets and of the user's themes, for completing `:theme`.
+pub fn names(settings: &settings::Theme) -> Vec<String> {
+    PRESETS
+        .iter()
+        .map(ToString::to_string)
+        .chain(settings.themes.keys().cloned())
+        .collect()
+}
+
+/// Whether the terminal has a light background, as far as `COLORFGBG` tells. Terminals that don't
+/// set it are assumed to be dark.
+fn is_light_background() -> bool {
+    std::env::var("COLORFGBG").is_ok_and(|colorfgbg| {
+        // `fg;bg`, or `fg;default;bg` in some terminals
+        colorfgbg
+            .rsplit(';')
+            .next()
+            .and_then(|background| background.parse::<u8>().ok())
+            .is_some_and(|background| matches!(background, 7 | 9..=15))
+    })
+}
+
+/// The RGB values that xterm uses for the 16 ANSI colors.
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The levels of each channel in the 6×6×6 color cube of the 256 colors.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The closest color to `color` that a terminal with this color depth can show.
+fn reduce(color: Color, depth: ColorDepth) -> Color {
+    match (depth, color) {
+        (ColorDepth::TrueColor | ColorDepth::Auto, _) => color,
+        (ColorDepth::Colors256, Color::Rgb(r, g, b)) => Color::Indexed(closest_indexed(r, g, b)),
+        (ColorDepth::Colors16, Color::Indexed(index)) if index < 16 => ANSI[usize::from(index)].0,
+        (ColorDepth::Colors16, Color::Indexed(index)) => {
+            let (r, g, b) = indexed_rgb(index);
+            closest_ansi(r, g, b)
+        }
+        (ColorDepth::Colors16, Color::Rgb(r, g, b)) => closest_ansi(r, g, b),
+        _ => color,
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+    channel(r1, r2) + channel(g1, g2) + channel(b1, b2)
+}
+
+fn closest_ansi(r: u8, g: u8, b: u8) -> Color {
+    ANSI.iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// The closest of the 256 colors, from the color cube or the gray ramp, leaving out the 16 ANSI
+/// colors as their exact colors vary between terminals.
+fn closest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let level = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|index| CUBE_LEVELS[*index].abs_diff(channel))
+            .unwrap_or_default()
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    // the gray ramp runs from 8 to 238 in steps of 10
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_index = u8::try_from(average.saturating_sub(3) / 10)
+        .unwrap_or(u8::MAX)
+        .min(23);
+    let gray_level = 8 + gray_index * 10;
+    let gray = (gray_level, gray_level, gray_level);
+    if distance(gray, (r, g, b)) < distance(cube, (r, g, b)) {
+        232 + gray_index
+    } else {
+        // the indices are less than 6, so the cube index is at most 231
+        16 + u8::try_from(36 * ri + 6 * gi + bi).unwrap_or_default()
+    }
+}
+
+/// The RGB value of one of the 256 colors past the 16 ANSI colors.
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    if index >= 232 {
+        let level = 8 + (index - 232) * 10;
+        (level, level, level)
+    } else {
+        let index = usize::from(index - 16);
+        (
+            CUBE_LEVELS[index / 36],
+            CUBE_LEVELS[index / 6 % 6],
+            CUBE_LEVELS[index % 6],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn reduces_colors_to_the_color_depth() {
+        let navy = Color::Rgb(0, 0, 95);
+        assert_eq!(reduce(navy, ColorDepth::TrueColor), navy);
+        assert_eq!(reduce(navy, ColorDepth::Colors256), Color::Indexed(17));
+        assert_eq!(
+            reduce(Color::Rgb(128, 128, 128), ColorDepth::Colors256),
+            Color::Indexed(244)
+        );
+        assert_eq!(reduce(navy, ColorDepth::Colors16), Color::Black);
+        assert_eq!(
+            reduce(Color::Indexed(9), ColorDepth::Colors16),
+            Color::LightRed
+        );
+        assert_eq!(
+            reduce(Color::Indexed(196), ColorDepth::Colors16),
+            Color::LightRed
+        );
+        assert_eq!(reduce(Color::Yellow, ColorDepth::Colors256), Color::Yellow);
+    }
+
+    #[test]
+    fn reverses_the_highlight_without_a_close_ansi_color() {
+        let theme = Theme::DARK.reduce(ColorDepth::Colors16);
+        assert_eq!(
+            theme.highlight(),
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+        let theme = Theme::HIGH_CONTRAST.reduce(ColorDepth::Colors16);
+        assert_eq!(
+            theme.highlight(),
+            Style::default().fg(Color::Black).bg(Color::White)
+        );
+    }
+
+    #[test]
+    fn user_themes_change_a_preset() {
+        let solarized = settings::UserTheme {
+            base: "light".to_string(),
+            colors: BTreeMap::from([(Slot::Accent, "#b58900".to_string())]),
+        };
+        let mut settings = settings::Theme {
+            name: "solarized".to_string(),
+            color_depth: ColorDepth::TrueColor,
+            themes: BTreeMap::from([("solarized".to_string(), solarized)]),
+        };
+        let theme = Theme::new(&settings).unwrap();
+        assert_eq!(theme.accent, Color::Rgb(0xb5, 0x89, 0x00));
+        assert_eq!(theme.error, Theme::LIGHT.error);
+
+        settings.name = "nope".to_string();
+        assert!(Theme::new(&settings).is_err());
+        settings.themes.get_mut("solarized").unwrap().colors =
+            BTreeMap::from([(Slot::Accent, "not a color".to_string())]);
+        settings.name = "solarized".to_string();
+        let err = Theme::new(&settings).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("theme.themes.solarized.colors.accent"));
+    }
+}
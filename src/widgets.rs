@@ -1,37 +1,41 @@
+use std::time::{Duration, Instant};
+
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Paragraph, Widget},
+    widgets::{Block, Clear, Paragraph, Widget},
 };
 
+use crate::{theme, wrap::wrap};
+
 pub struct StatusBar<'a> {
+    /// The keys of the main actions of the view, and their labels
+    hints: Vec<(String, &'static str)>,
     text: &'a str,
 }
 
 impl<'a> StatusBar<'a> {
     pub const HEIGHT: u16 = 1;
-    pub const fn new(text: &'a str) -> Self {
-        Self { text }
+    pub const fn new(hints: Vec<(String, &'static str)>, text: &'a str) -> Self {
+        Self { hints, text }
     }
 }
 
 impl<'a> Widget for StatusBar<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let style = Style::default().fg(Color::White).bg(Color::Blue);
+        let style = theme::current().bar();
         let bold = Style::default().add_modifier(Modifier::BOLD);
-        let text = Span::raw(self.text);
-        let text = Line::from(vec![
-            Span::styled("Esc ", bold),
-            Span::raw("quit | "),
-            Span::styled("J", bold),
-            Span::raw(" down | "),
-            Span::styled("K", bold),
-            Span::raw(" up | "),
-            text,
-        ]);
-        Paragraph::new(text).style(style).render(area, buf);
+        let mut spans = vec![];
+        for (keys, label) in self.hints {
+            spans.push(Span::styled(keys, bold));
+            spans.push(Span::raw(format!(" {label} | ")));
+        }
+        spans.push(Span::raw(self.text));
+        Paragraph::new(Line::from(spans))
+            .style(style)
+            .render(area, buf);
     }
 }
 
@@ -49,14 +53,63 @@ impl<'a> TitleBar<'a> {
 
 impl<'a> Widget for TitleBar<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let style = Style::default().fg(Color::White).bg(Color::Blue);
+        let theme = theme::current();
         let bold = Style::default().add_modifier(Modifier::BOLD);
-        let gray = Style::default().fg(Color::Gray);
+        let muted = Style::default().fg(theme.bar_muted);
         let text = Line::from(vec![
             Span::styled("Tooters", bold),
             Span::raw(" | "),
-            Span::styled(self.title, gray),
+            Span::styled(self.title, muted),
         ]);
-        Paragraph::new(text).style(style).render(area, buf);
+        Paragraph::new(text).style(theme.bar()).render(area, buf);
+    }
+}
+
+/// A message shown over the bottom right corner of the main view for a few seconds.
+#[derive(Debug)]
+pub struct Toast {
+    message: String,
+    shown_at: Instant,
+}
+
+impl Toast {
+    const DURATION: Duration = Duration::from_secs(5);
+    const MAX_WIDTH: u16 = 60;
+    const MAX_HEIGHT: u16 = 10;
+
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            shown_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.shown_at.elapsed() >= Self::DURATION
+    }
+}
+
+impl Widget for &Toast {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = Toast::MAX_WIDTH.min(area.width);
+        let lines: Vec<Line> = wrap(&self.message, usize::from(width.saturating_sub(2)))
+            .into_iter()
+            .map(|line| Line::raw(line.text))
+            .collect();
+        let height = u16::try_from(lines.len() + 2)
+            .unwrap_or(u16::MAX)
+            .min(Toast::MAX_HEIGHT)
+            .min(area.height);
+        let area = Rect {
+            x: area.right() - width,
+            y: area.bottom() - height,
+            width,
+            height,
+        };
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .block(Block::bordered())
+            .style(theme::current().bar())
+            .render(area, buf);
     }
 }
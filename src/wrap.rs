@@ -0,0 +1,190 @@
+use std::ops::Range;
+
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A single wrapped line of text in visual (display) order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedLine {
+    pub text: String,
+    /// Whether the line belongs to a right-to-left paragraph and so should be right aligned
+    pub rtl: bool,
+}
+
+/// Wraps each line of `text` so that it fits in `width` terminal columns.
+///
+/// Widths are measured per grapheme cluster using the same display widths as ratatui, so wide CJK
+/// characters and ZWJ emoji sequences take up two columns and are never split. Lines break at
+/// whitespace where possible, after wide characters (CJK text has no spaces between words), and
+/// otherwise at the last grapheme that fits.
+///
+/// Paragraphs containing right-to-left text are broken into lines in logical order and then each
+/// line is reordered for display using the Unicode bidi algorithm.
+pub fn wrap(text: &str, width: usize) -> Vec<WrappedLine> {
+    let width = width.max(1);
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let bidi = BidiInfo::new(paragraph, None);
+        let para = bidi.paragraphs.first();
+        let rtl = para.is_some_and(|para| para.level.is_rtl());
+        for range in break_lines(paragraph, width) {
+            let text = match para {
+                Some(para) if bidi.has_rtl() && !range.is_empty() => {
+                    bidi.reorder_line(para, range).into_owned()
+                }
+                _ => paragraph[range].to_string(),
+            };
+            lines.push(WrappedLine { text, rtl });
+        }
+    }
+    lines
+}
+
+/// Returns the byte ranges of each line when `paragraph` is wrapped to `width` columns.
+fn break_lines(paragraph: &str, width: usize) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut line_width = 0;
+    // the end of the line and the start of the next line at the last break opportunity
+    let mut last_break: Option<(usize, usize)> = None;
+    for (index, grapheme) in paragraph.grapheme_indices(true) {
+        let grapheme_width = grapheme.width();
+        let is_whitespace = grapheme.chars().all(char::is_whitespace);
+        if line_width + grapheme_width > width && index > start {
+            let (end, next) = match last_break {
+                Some((end, next)) if end > start => (end, next),
+                _ => (index, index),
+            };
+            ranges.push(start..trim_end(paragraph, start, end));
+            start = next;
+            line_width = paragraph[start..index].width();
+            last_break = None;
+        }
+        if is_whitespace {
+            if index == start && start != 0 {
+                // don't start a wrapped line with whitespace, but keep the indent of the first
+                start = index + grapheme.len();
+                continue;
+            }
+            last_break = Some((index, index + grapheme.len()));
+        } else if grapheme_width > 1 {
+            let end = index + grapheme.len();
+            last_break = Some((end, end));
+        }
+        line_width += grapheme_width;
+    }
+    if start < paragraph.len() || ranges.is_empty() {
+        ranges.push(start..trim_end(paragraph, start, paragraph.len()));
+    }
+    ranges
+}
+
+/// Returns the end of `paragraph[start..end]` with any trailing whitespace removed.
+fn trim_end(paragraph: &str, start: usize, end: usize) -> usize {
+    start + paragraph[start..end].trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[WrappedLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn wraps_latin_text_at_whitespace() {
+        let lines = wrap("the quick brown fox jumps", 10);
+        assert_eq!(texts(&lines), ["the quick", "brown fox", "jumps"]);
+        assert!(lines.iter().all(|line| !line.rtl));
+    }
+
+    #[test]
+    fn keeps_the_indent_of_paragraphs() {
+        let lines = wrap("  * the quick brown fox", 12);
+        assert_eq!(texts(&lines), ["  * the", "quick brown", "fox"]);
+        let lines = wrap("    code();\n  > quoted", 20);
+        assert_eq!(texts(&lines), ["    code();", "  > quoted"]);
+    }
+
+    #[test]
+    fn breaks_words_longer_than_the_width() {
+        let lines = wrap("abcdefghij", 4);
+        assert_eq!(texts(&lines), ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn keeps_blank_lines() {
+        let lines = wrap("one\n\ntwo", 10);
+        assert_eq!(texts(&lines), ["one", "", "two"]);
+    }
+
+    #[test]
+    fn wraps_cjk_by_display_width() {
+        // each character is two columns wide
+        let lines = wrap("日本語のテキストです", 8);
+        assert_eq!(texts(&lines), ["日本語の", "テキスト", "です"]);
+        for line in &lines {
+            assert!(line.text.width() <= 8);
+        }
+    }
+
+    #[test]
+    fn never_splits_a_wide_character_across_the_edge() {
+        let lines = wrap("a日本", 4);
+        assert_eq!(texts(&lines), ["a日", "本"]);
+    }
+
+    #[test]
+    fn keeps_zwj_emoji_sequences_together() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let lines = wrap(&format!("{family}{family}{family}"), 4);
+        assert_eq!(
+            texts(&lines),
+            [format!("{family}{family}"), family.to_string()]
+        );
+    }
+
+    #[test]
+    fn wraps_mixed_latin_and_cjk() {
+        let lines = wrap("hello 世界 rust 日本", 10);
+        assert_eq!(texts(&lines), ["hello 世界", "rust 日本"]);
+    }
+
+    #[test]
+    fn reorders_rtl_paragraphs() {
+        let lines = wrap("שלום", 10);
+        assert_eq!(
+            lines,
+            [WrappedLine {
+                text: "םולש".to_string(),
+                rtl: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn wraps_rtl_text_in_logical_order_before_reordering() {
+        // "مرحبا بالعالم" is "hello world" in Arabic
+        let lines = wrap("مرحبا بالعالم", 8);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.rtl));
+        assert_eq!(lines[0].text, "مرحبا".chars().rev().collect::<String>());
+        assert_eq!(lines[1].text, "بالعالم".chars().rev().collect::<String>());
+    }
+
+    #[test]
+    fn keeps_ltr_runs_in_order_within_rtl_paragraphs() {
+        let lines = wrap("שלום rust עולם", 20);
+        assert_eq!(texts(&lines), ["םלוע rust םולש"]);
+        assert!(lines[0].rtl);
+    }
+
+    #[test]
+    fn ltr_paragraphs_with_rtl_words_stay_left_aligned() {
+        let lines = wrap("say שלום to everyone", 40);
+        assert_eq!(texts(&lines), ["say םולש to everyone"]);
+        assert!(!lines[0].rtl);
+    }
+}